@@ -0,0 +1,111 @@
+use crate::{AssemblerError, LinkerError, MemoryMap, SourceFile, Span};
+
+/// symbol the default startup code jumps to
+pub const DEFAULT_ENTRY: &str = "main";
+
+/// name the built-in startup code is reported under in errors
+pub const NAME: &str = "<crt0>";
+
+/// the built-in startup code, placed before every program unless replaced
+///
+/// waits out the reset with a few nops, points $gv at the start of .data
+/// and $sp at the top of the stack, then calls entry
+pub fn default(entry: &str, memory: &MemoryMap) -> SourceFile {
+    let contents = format!(
+        "\
+.text
+    and $zero, $zero, $zero
+    and $zero, $zero, $zero
+    and $zero, $zero, $zero
+    and $zero, $zero, $zero
+    and $zero, $zero, $zero
+{}{}    ja $ra, {entry}
+",
+        load("$gv", memory.data_base),
+        load("$sp", memory.stack_top),
+    );
+
+    SourceFile::new(NAME, &contents)
+}
+
+/// report unknown identifiers in the built-in startup code as a missing entry symbol,
+/// the only identifier it refers to
+pub fn entry_errors(errors: Vec<AssemblerError>, sources: &[SourceFile]) -> Vec<AssemblerError> {
+    let in_crt0 = |span: &Span| sources.get(span.file).is_some_and(|s| s.name == NAME);
+
+    errors
+        .into_iter()
+        .map(|e| match e {
+            AssemblerError::LinkerError(LinkerError::UnknownIdentifier(id, span))
+                if in_crt0(&span) =>
+            {
+                AssemblerError::LinkerError(LinkerError::EntryNotFound(id, span))
+            }
+            e => e,
+        })
+        .collect()
+}
+
+/// load a constant address into register,
+/// a single lui is enough for addresses with the lower half cleared
+fn load(register: &str, address: u32) -> String {
+    if address & 0xffff == 0 {
+        format!("    lui {register}, 0x{:x}\n", address >> 16)
+    } else {
+        format!("    la {register}, 0x{address:x}\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble_sources, Options};
+
+    // tests if the stack and global pointers follow the memory map
+    #[test]
+    fn pointers() {
+        let memory = MemoryMap {
+            data_base: 0x2000_0000,
+            stack_top: 0x2000_8000,
+            ..MemoryMap::default()
+        };
+        let contents = default(DEFAULT_ENTRY, &memory).contents;
+
+        assert!(contents.contains("    lui $gv, 0x2000\n"));
+        assert!(contents.contains("    la $sp, 0x20008000\n"));
+    }
+
+    // tests if the startup code calls the configured entry symbol
+    #[test]
+    fn entry() {
+        let memory = MemoryMap::default();
+        let program = |entry| SourceFile::new("main.s", &format!("{entry}:\n    j $zero, $ra\n"));
+        let assembled =
+            |sources: &[SourceFile]| assemble_sources(sources, &Options::default()).unwrap();
+
+        assert_eq!(
+            assembled(&[default(DEFAULT_ENTRY, &memory), program("main")]),
+            assembled(&[default("start", &memory), program("start")])
+        );
+
+        let sources = [default("start", &memory), program("main")];
+        let errors = assemble_sources(&sources, &Options::default()).unwrap_err();
+        let errors = entry_errors(errors, &sources);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "Error: Entry symbol not found: \"start\""
+        );
+
+        // the entry is only missing if the startup code is the one looking for it
+        let sources = [
+            SourceFile::new("call.s", ".text\n    ja $ra, main\n"),
+            program("start"),
+        ];
+        let errors = assemble_sources(&sources, &Options::default()).unwrap_err();
+        assert_eq!(
+            entry_errors(errors, &sources)[0].to_string(),
+            "Error: Identifier not recognized: \"main\""
+        );
+    }
+}
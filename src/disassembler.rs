@@ -0,0 +1,844 @@
+use crate::linker::fits_single;
+use crate::types::*;
+use crate::MemoryMap;
+use crate::Span;
+use std::collections::BTreeSet;
+
+/// instructions, indexed by opcode
+const OPCODES: [Instr; 16] = [
+    Instr::And,
+    Instr::Or,
+    Instr::Xor,
+    Instr::Not,
+    Instr::Add,
+    Instr::Sub,
+    Instr::Cmp,
+    Instr::J,
+    Instr::Beq,
+    Instr::Bne,
+    Instr::Sl,
+    Instr::Sr,
+    Instr::Addi,
+    Instr::Lui,
+    Instr::Lw,
+    Instr::Sw,
+];
+
+/// largest number of words a memory read back from a HEX or S-record file may hold
+const MAX_MEMORY_WORDS: u32 = 1 << 24;
+
+/// # Disassembler errors
+///
+/// raised when reading a HEX or S-record file back, each holds the line number of the offending record
+#[derive(Debug, PartialEq)]
+pub enum DisassemblerError {
+    InvalidRecord(usize),
+    InvalidChecksum(usize),
+    UnsupportedRecord(usize, u8),
+    MisalignedRecord(usize),
+    AddressOutOfRange(usize, u32),
+}
+
+impl std::fmt::Display for DisassemblerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidRecord(line) => write!(f, "Malformed record on line {line}"),
+            Self::InvalidChecksum(line) => write!(f, "Checksum mismatch on line {line}"),
+            Self::UnsupportedRecord(line, t) => {
+                write!(f, "Unsupported record type {t:02X} on line {line}")
+            }
+            Self::MisalignedRecord(line) => {
+                write!(f, "Record on line {line} does not hold whole words")
+            }
+            Self::AddressOutOfRange(line, addr) => write!(
+                f,
+                "Address 0x{addr:08X} on line {line} lies beyond the end of its memory"
+            ),
+        }
+    }
+}
+
+/// # Read HEX
+///
+/// convert an intel HEX file, as written by printer::print_hex, back to binary form
+///
+/// word addresses below offset belong to the instruction memory, the rest to the data memory.
+/// Gaps between records are filled with zeroes
+pub fn read_hex(hex: &str, offset: u32) -> Result<ProgramBinary, DisassemblerError> {
+    let memory = MemoryMap {
+        physical_data_offset: offset,
+        ..MemoryMap::default()
+    };
+    read_hex_with(hex, &memory)
+}
+
+/// convert an intel HEX file back to binary form,
+/// splitting the memories at the physical data offset of the memory map
+///
+/// words that do not fit in the size of their segment are rejected
+pub fn read_hex_with(hex: &str, memory: &MemoryMap) -> Result<ProgramBinary, DisassemblerError> {
+    let mut binary = ProgramBinary {
+        instructions: Vec::new(),
+        data: Vec::new(),
+    };
+
+    // address selected by the last extended address record
+    let mut base: u32 = 0;
+
+    for (i, line) in hex.lines().enumerate() {
+        let number = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // byte count, two address bytes, record type, data, checksum
+        let bytes = match parse_record(line) {
+            Some(bytes) if bytes.len() >= 5 && bytes.len() == bytes[0] as usize + 5 => bytes,
+            _ => return Err(DisassemblerError::InvalidRecord(number)),
+        };
+
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(DisassemblerError::InvalidChecksum(number));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+
+        match (bytes[3], data) {
+            (0x00, data) if data.len() % 4 == 0 => {
+                for (j, word) in data.chunks(4).enumerate() {
+                    let addr = base.wrapping_add(address).wrapping_add(j as u32);
+                    let word = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+                    store(&mut binary, memory, addr, word)
+                        .ok_or(DisassemblerError::AddressOutOfRange(number, addr))?;
+                }
+            }
+            (0x00, _) => return Err(DisassemblerError::MisalignedRecord(number)),
+            // end of file, anything after it is ignored
+            (0x01, _) => break,
+            // extended segment address, holds the base address divided by 16
+            (0x02, &[high, low]) => base = (u16::from_be_bytes([high, low]) as u32) << 4,
+            // extended linear address, holds the upper 16 bits of the address
+            (0x04, &[high, low]) => base = (u16::from_be_bytes([high, low]) as u32) << 16,
+            (0x02 | 0x04, _) => return Err(DisassemblerError::InvalidRecord(number)),
+            // start addresses do not affect the memory contents
+            (0x03 | 0x05, _) => (),
+            (t, _) => return Err(DisassemblerError::UnsupportedRecord(number, t)),
+        }
+    }
+
+    Ok(binary)
+}
+
+/// # Read S-records
+///
+/// convert a motorola S-record file, as written by printer::print_srec, back to binary form
+///
+/// S1, S2 and S3 records are all accepted, memories are split at offset as in read_hex
+pub fn read_srec(srec: &str, offset: u32) -> Result<ProgramBinary, DisassemblerError> {
+    let memory = MemoryMap {
+        physical_data_offset: offset,
+        ..MemoryMap::default()
+    };
+    read_srec_with(srec, &memory)
+}
+
+/// convert a motorola S-record file back to binary form,
+/// splitting the memories at the physical data offset of the memory map
+///
+/// words that do not fit in the size of their segment are rejected
+pub fn read_srec_with(srec: &str, memory: &MemoryMap) -> Result<ProgramBinary, DisassemblerError> {
+    let mut binary = ProgramBinary {
+        instructions: Vec::new(),
+        data: Vec::new(),
+    };
+
+    for (i, line) in srec.lines().enumerate() {
+        let number = i + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // record type, then byte count, address, data, checksum
+        let (kind, bytes) = match line.strip_prefix('S').and_then(|rest| {
+            let kind = rest.get(..1)?.parse::<u8>().ok()?;
+            Some((kind, parse_digits(&rest[1..])?))
+        }) {
+            Some((kind, bytes)) if bytes.len() >= 2 && bytes.len() == bytes[0] as usize + 1 => {
+                (kind, bytes)
+            }
+            _ => return Err(DisassemblerError::InvalidRecord(number)),
+        };
+
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0xFF {
+            return Err(DisassemblerError::InvalidChecksum(number));
+        }
+
+        let width = match kind {
+            // header, counts and terminators do not affect the memory contents
+            0 | 5..=9 => continue,
+            1..=3 => kind as usize + 1,
+            t => return Err(DisassemblerError::UnsupportedRecord(number, t)),
+        };
+
+        let Some(record) = bytes.get(1..bytes.len() - 1).filter(|r| r.len() >= width) else {
+            return Err(DisassemblerError::InvalidRecord(number));
+        };
+        let (address, data) = record.split_at(width);
+        let address = address.iter().fold(0u32, |addr, b| addr << 8 | *b as u32);
+
+        if data.len() % 4 != 0 {
+            return Err(DisassemblerError::MisalignedRecord(number));
+        }
+
+        for (j, word) in data.chunks(4).enumerate() {
+            let addr = address.wrapping_add(j as u32);
+            let word = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+            store(&mut binary, memory, addr, word)
+                .ok_or(DisassemblerError::AddressOutOfRange(number, addr))?;
+        }
+    }
+
+    Ok(binary)
+}
+
+/// writes a word to the memory its address belongs to, growing it with zeroes as needed
+///
+/// None if the address lies beyond the size of its segment in the memory map
+fn store(binary: &mut ProgramBinary, map: &MemoryMap, addr: u32, word: u32) -> Option<()> {
+    let offset = map.physical_data_offset;
+    let (memory, index, size) = if addr < offset {
+        (&mut binary.instructions, addr, map.text_size)
+    } else {
+        (&mut binary.data, addr - offset, map.data_size)
+    };
+
+    if index >= MAX_MEMORY_WORDS.min(size / 4) {
+        return None;
+    }
+
+    if memory.len() <= index as usize {
+        memory.resize(index as usize + 1, 0);
+    }
+    memory[index as usize] = word;
+    Some(())
+}
+
+/// split a record into its bytes, None if it is not a colon followed by pairs of hex digits
+fn parse_record(line: &str) -> Option<Vec<u8>> {
+    parse_digits(line.strip_prefix(':')?)
+}
+
+/// split pairs of hex digits into bytes, None on any other character
+fn parse_digits(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// # Decode
+///
+/// convert one instruction word back to its abstract representation
+///
+/// immediates of addi and branches are sign extended, all others are read as unsigned
+pub fn decode(word: u32, address: u32) -> InstructionNode {
+    let op = OPCODES[(word >> 28) as usize].clone();
+
+    let imm = match op {
+        Instr::Addi | Instr::Beq | Instr::Bne => (word & 0xffff) as u16 as i16 as i64,
+        _ => (word & 0xffff) as i64,
+    };
+
+    InstructionNode {
+        op,
+        rd: ((word >> 24) & 0b1111) as u8,
+        ra: ((word >> 20) & 0b1111) as u8,
+        rb: ((word >> 16) & 0b1111) as u8,
+        imm,
+        identifier: String::new(),
+        label_span: Span::default(),
+        imm_expr: None,
+        address,
+        placement: None,
+        span: Span::default(),
+    }
+}
+
+/// # Pseudoinstruction
+///
+/// a pseudoinstruction recognised from the instructions it expands to
+///
+/// value holds the address loaded by la and ja,
+/// short is set for the sequences relaxation shortens them to
+#[derive(Debug, PartialEq)]
+struct Pseudo {
+    op: Instr,
+    reg: u8,
+    value: u32,
+    len: usize,
+    short: bool,
+}
+
+/// check whether the instructions at the start of nodes are the expansion of a pseudoinstruction
+fn recognise(nodes: &[InstructionNode]) -> Option<Pseudo> {
+    use Instr::*;
+
+    let fields: Vec<(Instr, u8, u8, u8, i64)> = nodes
+        .iter()
+        .take(6)
+        .map(|n| (n.op.clone(), n.rd, n.ra, n.rb, n.imm))
+        .collect();
+
+    // la and ja load the upper half through $at, then clear the sign extension of the lower half
+    let address = |hi: i64, lo: i64| ((hi as u32) << 16) | (lo as u32 & 0xffff);
+
+    // shortened, the lower half is added to the adjusted upper half
+    let adjusted = |hi: i64, lo: i64| ((hi as u32) << 16).wrapping_add(lo as u32);
+
+    // relaxation only picks a pair of loads if a single one does not reach the value
+    let pair = |hi: i64, lo: i64| !fits_single(adjusted(hi, lo) as i64);
+    let short = |op, reg, value, len| {
+        Some(Pseudo {
+            op,
+            reg,
+            value,
+            len,
+            short: true,
+        })
+    };
+
+    match fields[..] {
+        [(Lui, 1, 0, 0, hi), (Addi, r, 0, 0, lo), (Sl, r1, r2, 0, 16), (Sr, r3, r4, 0, 16), (Add, 1, 1, r5, 0), (J, r6, 1, 0, 0), ..]
+            if [r1, r2, r3, r4, r5, r6].iter().all(|x| *x == r) =>
+        {
+            Some(Pseudo {
+                op: Ja,
+                reg: r,
+                value: address(hi, lo),
+                len: 6,
+                short: false,
+            })
+        }
+        [(Lui, 1, 0, 0, hi), (Addi, r, 0, 0, lo), (Sl, r1, r2, 0, 16), (Sr, r3, r4, 0, 16), (Add, r5, 1, r6, 0), ..]
+            if [r1, r2, r3, r4, r5, r6].iter().all(|x| *x == r) =>
+        {
+            Some(Pseudo {
+                op: La,
+                reg: r,
+                value: address(hi, lo),
+                len: 5,
+                short: false,
+            })
+        }
+        [(Addi, 15, 15, 0, -4), (Sw, 0, r, 15, 0), ..] => Some(Pseudo {
+            op: Push,
+            reg: r,
+            value: 0,
+            len: 2,
+            short: false,
+        }),
+        [(Lw, r, 0, 15, 0), (Addi, 15, 15, 0, 4), ..] => Some(Pseudo {
+            op: Pop,
+            reg: r,
+            value: 0,
+            len: 2,
+            short: false,
+        }),
+        [(Lui, 1, 0, 0, hi), (Addi, 1, 1, 0, lo), (J, r, 1, 0, 0), ..] if pair(hi, lo) => {
+            short(Ja, r, adjusted(hi, lo), 3)
+        }
+        [(Lui, 1, 0, 0, hi), (J, r, 1, 0, 0), ..] if hi != 0 => short(Ja, r, adjusted(hi, 0), 2),
+        [(Addi, 1, 0, 0, lo), (J, r, 1, 0, 0), ..] => short(Ja, r, adjusted(0, lo), 2),
+        [(Lui, r, 0, 0, hi), (Addi, r1, r2, 0, lo), ..]
+            if r != 0 && r1 == r && r2 == r && pair(hi, lo) =>
+        {
+            short(La, r, adjusted(hi, lo), 2)
+        }
+        [(Lui, r, 0, 0, hi), ..] if r != 0 && hi != 0 => short(La, r, adjusted(hi, 0), 1),
+        [(Addi, r, 0, 0, lo), ..] if r != 0 => short(La, r, adjusted(0, lo), 1),
+        _ => None,
+    }
+}
+
+/// # Disassemble
+///
+/// convert a binary back to a program listing, which assembles to the same binary
+///
+/// branch, la and ja targets inside the program get synthesised labels,
+/// and the expansions of la, ja, push and pop are folded back into the pseudoinstructions,
+/// as are the sequences relaxation shortens la and ja to.
+/// Every line is followed by the address and the words it was decoded from.
+/// The segments are taken to start at the bases of the default memory map
+pub fn disassemble(binary: &ProgramBinary) -> String {
+    disassemble_with(binary, &MemoryMap::default())
+}
+
+/// convert a binary back to a program listing,
+/// with the segments starting at the bases given in memory
+///
+/// each memory has to fit in the size of its segment, as read_hex_with makes sure of
+pub fn disassemble_with(binary: &ProgramBinary, memory: &MemoryMap) -> String {
+    let nodes: Vec<InstructionNode> = binary
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(i, word)| decode(*word, memory.text_base + 4 * i as u32))
+        .collect();
+
+    let text_end = memory.text_base as u64 + 4 * nodes.len() as u64;
+    let data_end = memory.data_base as u64 + 4 * binary.data.len() as u64;
+    let inside = |addr: i64| {
+        (memory.text_base as i64..text_end as i64).contains(&addr)
+            || (memory.data_base as i64..data_end as i64).contains(&addr)
+    };
+
+    // a relaxed program has no la or ja at full length left.
+    // The short sequences could just as well be plain instructions,
+    // so they are only taken for la and ja if they load an address inside the program
+    let relaxed = (0..nodes.len()).all(|i| {
+        recognise(&nodes[i..]).is_none_or(|p| p.short || !matches!(p.op, Instr::La | Instr::Ja))
+    });
+    let recognise = |nodes: &[InstructionNode]| {
+        recognise(nodes).filter(|p| !p.short || (relaxed && inside(p.value as i64)))
+    };
+
+    // every address referred to by the program gets a label
+    let mut labels = BTreeSet::new();
+    let mut i = 0;
+    while i < nodes.len() {
+        match recognise(&nodes[i..]) {
+            Some(pseudo) => {
+                labels.insert(pseudo.value as i64);
+                i += pseudo.len;
+            }
+            None => {
+                if let Instr::Beq | Instr::Bne = nodes[i].op {
+                    labels.insert(nodes[i].address as i64 + nodes[i].imm);
+                }
+                i += 1;
+            }
+        }
+    }
+    labels.retain(|addr| inside(*addr));
+
+    let label = |addr: i64| format!("L_{addr:08x}");
+
+    let mut listing = String::from(".text\n");
+
+    let mut i = 0;
+    while i < nodes.len() {
+        let node = &nodes[i];
+        if labels.contains(&(node.address as i64)) {
+            listing.push_str(&format!("{}:\n", label(node.address as i64)));
+        }
+
+        // a pseudoinstruction can only be folded if nothing jumps into the middle of it
+        let pseudo = recognise(&nodes[i..]).filter(|p| {
+            nodes[i + 1..i + p.len]
+                .iter()
+                .all(|n| !labels.contains(&(n.address as i64)))
+        });
+
+        let (text, len) = match pseudo {
+            Some(p) => {
+                let reg = REGISTER_NAMES[p.reg as usize];
+                let text = match p.op {
+                    Instr::La | Instr::Ja if labels.contains(&(p.value as i64)) => {
+                        format!("{} ${reg}, {}", mnemonic(&p.op), label(p.value as i64))
+                    }
+                    Instr::La | Instr::Ja => {
+                        format!("{} ${reg}, 0x{:08x}", mnemonic(&p.op), p.value)
+                    }
+                    _ => format!("{} ${reg}", mnemonic(&p.op)),
+                };
+                (text, p.len)
+            }
+            None => {
+                let target = node.address as i64 + node.imm;
+                let text = match node.op {
+                    Instr::Beq | Instr::Bne if labels.contains(&target) => {
+                        format!("{}, {}", print_operands(node), label(target))
+                    }
+                    _ => print_instruction(node),
+                };
+                (text, 1)
+            }
+        };
+
+        let words: Vec<String> = binary.instructions[i..i + len]
+            .iter()
+            .map(|w| format!("{w:08X}"))
+            .collect();
+        listing.push_str(&format!(
+            "    {text:<28}# {:08x}: {}\n",
+            node.address,
+            words.join(" ")
+        ));
+
+        i += len;
+    }
+
+    if binary.data.is_empty() {
+        return listing;
+    }
+
+    listing.push_str(".data\n");
+
+    let mut i = 0;
+    while i < binary.data.len() {
+        let addr = (memory.data_base + 4 * i as u32) as i64;
+        if labels.contains(&addr) {
+            listing.push_str(&format!("{}:\n", label(addr)));
+        }
+
+        // runs of the same word are collected into one block, up to the next label:
+        // .space for zeroes, a repeated .word for anything else
+        let word = binary.data[i];
+        let run = binary.data[i..]
+            .iter()
+            .enumerate()
+            .take_while(|(j, w)| {
+                **w == word && (*j == 0 || !labels.contains(&(addr + 4 * *j as i64)))
+            })
+            .count();
+
+        let (text, len) = match (word, run) {
+            (0, 1) => (".word 0".to_string(), 1),
+            (0, n) => (format!(".space {n}"), n),
+            (w, 1) => (format!(".word 0x{w:08x}"), 1),
+            (w, n) => (format!(".word 0x{w:08x} : {n}"), n),
+        };
+        listing.push_str(&format!("    {text:<28}# {addr:08x}\n"));
+
+        i += len;
+    }
+
+    listing
+}
+
+/// the name of an instruction as it is written in the listing
+fn mnemonic(op: &Instr) -> String {
+    format!("{op:?}").to_lowercase()
+}
+
+/// print an instruction without its branch target
+fn print_operands(node: &InstructionNode) -> String {
+    let reg = |r: u8| format!("${}", REGISTER_NAMES[r as usize]);
+
+    match node.op {
+        Instr::Beq | Instr::Bne => {
+            format!("{} {}, {}", mnemonic(&node.op), reg(node.ra), reg(node.rb))
+        }
+        _ => print_instruction(node),
+    }
+}
+
+/// print a single instruction in the form it is written in the listing
+pub(crate) fn print_instruction(node: &InstructionNode) -> String {
+    let reg = |r: u8| format!("${}", REGISTER_NAMES[r as usize]);
+    let op = mnemonic(&node.op);
+
+    match node.op {
+        Instr::And | Instr::Or | Instr::Xor | Instr::Add | Instr::Sub | Instr::Cmp => {
+            format!("{op} {}, {}, {}", reg(node.rd), reg(node.ra), reg(node.rb))
+        }
+        Instr::Not | Instr::J => format!("{op} {}, {}", reg(node.rd), reg(node.ra)),
+        Instr::Sl | Instr::Sr | Instr::Addi => {
+            format!("{op} {}, {}, {}", reg(node.rd), reg(node.ra), node.imm)
+        }
+        Instr::Beq | Instr::Bne => format!("{}, {}", print_operands(node), node.imm),
+        Instr::Lui => format!("{op} {}, 0x{:04x}", reg(node.rd), node.imm),
+        Instr::Lw => format!("{op} {}, {}", reg(node.rd), reg(node.rb)),
+        Instr::Sw => format!("{op} {}, {}", reg(node.ra), reg(node.rb)),
+        Instr::La | Instr::Ja | Instr::Push | Instr::Pop => {
+            panic!("Pseudoinstruction not expanded: {:?}", node.op)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::print_hex;
+    use crate::{Options, SourceFile};
+
+    #[test]
+    fn decoding() {
+        assert_eq!(
+            decode(0x8016_FFFC, 8),
+            InstructionNode {
+                op: Instr::Beq,
+                rd: 0,
+                ra: 1,
+                rb: 6,
+                imm: -4,
+                identifier: String::new(),
+                label_span: Span::default(),
+                imm_expr: None,
+                address: 8,
+                placement: None,
+                span: Span::default(),
+            }
+        );
+
+        assert_eq!(decode(0xD100_F000, 0).imm, 0xf000);
+    }
+
+    #[test]
+    fn hex_reading() {
+        let binary = ProgramBinary {
+            instructions: vec![0xD100_1000, 0xC150_0004],
+            data: vec![5, 0, 0xdeadbeef],
+        };
+
+        assert_eq!(
+            read_hex(&print_hex(binary.clone(), 0x1_0000), 0x1_0000),
+            Ok(binary)
+        );
+
+        assert_eq!(
+            read_hex(":0400000000000001FB\n:020000020000FC\n", 4),
+            Ok(ProgramBinary {
+                instructions: vec![1],
+                data: vec![],
+            })
+        );
+
+        assert_eq!(
+            read_hex(":0400000000000001FB\n0400", 4),
+            Err(DisassemblerError::InvalidRecord(2))
+        );
+        assert_eq!(
+            read_hex(":0400000000000001FC", 4),
+            Err(DisassemblerError::InvalidChecksum(1))
+        );
+        assert_eq!(
+            read_hex(":020000000001FD", 4),
+            Err(DisassemblerError::MisalignedRecord(1))
+        );
+        assert_eq!(
+            read_hex(":00000006FA", 4),
+            Err(DisassemblerError::UnsupportedRecord(1, 6))
+        );
+        assert_eq!(
+            read_hex(":02000004FFFFFC\n:0400000000000001FB", 4),
+            Err(DisassemblerError::AddressOutOfRange(2, 0xffff_0000))
+        );
+    }
+
+    // tests if words beyond the size of their segment are rejected instead of overflowing
+    #[test]
+    fn segment_sizes() {
+        let memory = MemoryMap {
+            data_base: 0xFFFF_0000,
+            data_size: 0x1_0000,
+            physical_data_offset: 0x400,
+            ..MemoryMap::default()
+        };
+        memory.validate().unwrap();
+
+        let binary = read_hex_with(":0443FF0000000001B9\n", &memory).unwrap();
+        assert_eq!(binary.data.len(), 0x4000);
+        assert!(disassemble_with(&binary, &memory).ends_with("# fffffffc\n"));
+
+        assert_eq!(
+            read_hex_with(":0444000000000001B7\n", &memory),
+            Err(DisassemblerError::AddressOutOfRange(1, 0x4400))
+        );
+        assert_eq!(
+            read_srec_with("S107440000000001B3\n", &memory),
+            Err(DisassemblerError::AddressOutOfRange(1, 0x4400))
+        );
+    }
+
+    #[test]
+    fn srec_reading() {
+        assert_eq!(
+            read_srec("S0030000FC\nS107000200000001F5\nS9030000FC\n", 4),
+            Ok(ProgramBinary {
+                instructions: vec![0, 0, 1],
+                data: vec![],
+            })
+        );
+
+        assert_eq!(
+            read_srec("S107000200000001F6", 4),
+            Err(DisassemblerError::InvalidChecksum(1))
+        );
+        assert_eq!(
+            read_srec("S0030000FC\nS10700", 4),
+            Err(DisassemblerError::InvalidRecord(2))
+        );
+        assert_eq!(
+            read_srec("S1050002000AEE", 4),
+            Err(DisassemblerError::MisalignedRecord(1))
+        );
+        assert_eq!(
+            read_srec("S4030000FC", 4),
+            Err(DisassemblerError::UnsupportedRecord(1, 4))
+        );
+    }
+
+    #[test]
+    fn pseudoinstructions() {
+        let nodes: Vec<InstructionNode> = [
+            0xD100_1000, // lui $at, 0x1000
+            0xC900_0004, // addi $t0, $zero, 4
+            0xA990_0010, // sl $t0, $t0, 16
+            0xB990_0010, // sr $t0, $t0, 16
+            0x4919_0000, // add $t0, $at, $t0
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, w)| decode(*w, 4 * i as u32))
+        .collect();
+
+        assert_eq!(
+            recognise(&nodes),
+            Some(Pseudo {
+                op: Instr::La,
+                reg: 9,
+                value: 0x1000_0004,
+                len: 5,
+                short: false
+            })
+        );
+        // cut short, only the load of the upper half is left
+        assert_eq!(
+            recognise(&nodes[..4]).map(|p| (p.len, p.short)),
+            Some((1, true))
+        );
+
+        let decode_all = |words: &[u32]| -> Vec<InstructionNode> {
+            words.iter().map(|w| decode(*w, 0)).collect()
+        };
+
+        // lui $at, 0x1235; addi $at, $at, -0x6544; j $ra, $at
+        let nodes = decode_all(&[0xD100_1235, 0xC110_9ABC, 0x7E10_0000]);
+        assert_eq!(
+            recognise(&nodes),
+            Some(Pseudo {
+                op: Instr::Ja,
+                reg: 14,
+                value: 0x1234_9abc,
+                len: 3,
+                short: true
+            })
+        );
+
+        // addi $t0, $zero, 0x40
+        let nodes = decode_all(&[0xC900_0040]);
+        assert_eq!(
+            recognise(&nodes).map(|p| (p.op, p.value)),
+            Some((Instr::La, 0x40))
+        );
+
+        // lui $t0, 0x1000
+        let nodes = decode_all(&[0xD900_1000]);
+        assert_eq!(
+            recognise(&nodes).map(|p| (p.op, p.value)),
+            Some((Instr::La, 0x1000_0000))
+        );
+
+        // a pair is never picked for a value a single instruction loads:
+        // lui $t0, 0x1000; addi $t0, $t0, 0
+        let nodes = decode_all(&[0xD900_1000, 0xC990_0000]);
+        assert_eq!(recognise(&nodes).map(|p| p.len), Some(1));
+    }
+
+    #[test]
+    fn disassembly() {
+        let binary = ProgramBinary {
+            instructions: vec![0xCFF0_FFFC, 0xF09F_0000, 0x8010_FFF8, 0xE90F_0000],
+            data: vec![5, 0, 0],
+        };
+
+        assert_eq!(
+            disassemble(&binary),
+            ".text\n\
+             L_00000000:\n    \
+             push $t0                    # 00000000: CFF0FFFC F09F0000\n    \
+             beq $at, $zero, L_00000000  # 00000008: 8010FFF8\n    \
+             lw $t0, $sp                 # 0000000c: E90F0000\n\
+             .data\n    \
+             .word 0x00000005            # 10000000\n    \
+             .space 2                    # 10000004\n"
+        );
+    }
+
+    // tests if a disassembled program assembles back to the same binary
+    #[test]
+    fn roundtrip() {
+        let listing = "
+.data
+number: .word 0xdeadbeef
+buffer: .space 4
+table: .word 7 : 3, number, 7
+last: .word -1
+.text
+main:
+    la $t0, number
+    lw $t1, $t0
+loop:
+    addi $t1, $t1, -1
+    push $t1
+    pop $t2
+    bne $t1, $zero, loop
+    lui $t3, 0x8000
+    sl $t3, $t3, 3
+    ja $ra, main
+    beq $zero, $zero, 0x7ff0
+";
+        // la and ja are only folded back at their full length
+        let options = Options {
+            relax: false,
+            ..Options::default()
+        };
+        let assemble =
+            |listing: &str| crate::assemble_sources(&[SourceFile::new("", listing)], &options);
+
+        let hex = assemble(listing).unwrap();
+        let binary = read_hex(&hex, 0x400).unwrap();
+        let disassembly = disassemble(&binary);
+
+        assert_eq!(assemble(&disassembly), Ok(hex));
+        assert!(disassembly.contains("ja $ra, L_00000000"));
+        assert!(disassembly.contains("la $t0, L_10000000"));
+        assert!(disassembly.contains("bne $t1, $zero, L_00000018"));
+        assert!(disassembly.contains("beq $zero, $zero, 32752"));
+        assert!(disassembly.contains(".word 0x00000007 : 3"));
+
+        // shortened sequences are folded back as well
+        let hex = crate::assemble(listing, 0x400).unwrap();
+        let disassembly = disassemble(&read_hex(&hex, 0x400).unwrap());
+
+        assert_eq!(crate::assemble(&disassembly, 0x400), Ok(hex));
+        assert!(disassembly.contains("ja $ra, L_00000000"));
+        assert!(disassembly.contains("la $t0, L_10000000"));
+        assert!(disassembly.contains("lui $t3, 0x8000"));
+
+        // the pair relaxation needs for an address further away
+        let far = "
+.data
+    .space 17
+far: .word 1
+.text
+main:
+    la $t0, far
+    ja $ra, main
+";
+        let hex = crate::assemble(far, 0x400).unwrap();
+        let disassembly = disassemble(&read_hex(&hex, 0x400).unwrap());
+
+        assert_eq!(crate::assemble(&disassembly, 0x400), Ok(hex));
+        assert!(disassembly.contains("la $t0, L_10000044"));
+        assert!(disassembly.contains("ja $ra, L_00000000"));
+    }
+}
@@ -0,0 +1,240 @@
+/// # Operators
+///
+/// Add, Sub and Not can also be used as unary operators.
+/// Hi, Lo and Hia are relocation operators, written as %hi(x), %lo(x) and %hia(x)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+    Not,
+    Hi,
+    Lo,
+    Hia,
+}
+
+impl Op {
+    /// how tightly the operator binds when placed between two operands, following C
+    ///
+    /// None for operators that can only be unary
+    pub fn precedence(&self) -> Option<u8> {
+        match self {
+            Op::Mul | Op::Div | Op::Rem => Some(5),
+            Op::Add | Op::Sub => Some(4),
+            Op::Shl | Op::Shr => Some(3),
+            Op::And => Some(2),
+            Op::Xor => Some(1),
+            Op::Or => Some(0),
+            Op::Not | Op::Hi | Op::Lo | Op::Hia => None,
+        }
+    }
+
+    /// apply the operator to a single operand
+    ///
+    /// %hi is the upper half of a word, fitting lui.
+    /// %lo is the lower half, sign extended so it fits addi.
+    /// %hia is the upper half adjusted for that sign extension,
+    /// so lui with %hia followed by addi with %lo loads the whole word
+    pub fn unary(&self, x: i64) -> i64 {
+        match self {
+            Op::Sub => x.wrapping_neg(),
+            Op::Not => !x,
+            Op::Hi => (x >> 16) & 0xffff,
+            Op::Lo => ((x & 0xffff) ^ 0x8000) - 0x8000,
+            Op::Hia => ((x >> 16) + ((x >> 15) & 1)) & 0xffff,
+            _ => x,
+        }
+    }
+}
+
+/// # Expression
+///
+/// operand of an instruction or a data block, made up of values, identifiers and operators
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Expr {
+    Value(i64),
+    Identifier(String),
+    Unary(Op, Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+/// # Expression errors
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ExprError {
+    UnknownIdentifier(String),
+    DivisionByZero,
+    InvalidShift(i64),
+    Overflow,
+}
+
+impl std::fmt::Display for ExprError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UnknownIdentifier(id) => write!(f, "Identifier not recognized: \"{id}\""),
+            Self::DivisionByZero => write!(f, "Division by zero"),
+            Self::InvalidShift(amount) => {
+                write!(f, "Shift amount {amount} does not lie between 0 and 63")
+            }
+            Self::Overflow => write!(f, "Expression does not fit in 64 bits"),
+        }
+    }
+}
+
+impl Expr {
+    /// compute the value of the expression, looking up identifiers with lookup
+    ///
+    /// arithmetic is performed on 64 bit signed values, results that do not fit are an error,
+    /// right shifts are arithmetic
+    pub fn evaluate(&self, lookup: &impl Fn(&str) -> Option<i64>) -> Result<i64, ExprError> {
+        match self {
+            Expr::Value(x) => Ok(*x),
+            Expr::Identifier(id) => {
+                lookup(id).ok_or_else(|| ExprError::UnknownIdentifier(id.clone()))
+            }
+            Expr::Unary(Op::Sub, operand) => operand
+                .evaluate(lookup)?
+                .checked_neg()
+                .ok_or(ExprError::Overflow),
+            Expr::Unary(op, operand) => Ok(op.unary(operand.evaluate(lookup)?)),
+            Expr::Binary(op, lhs, rhs) => {
+                let a = lhs.evaluate(lookup)?;
+                let b = rhs.evaluate(lookup)?;
+                match op {
+                    Op::Add => a.checked_add(b).ok_or(ExprError::Overflow),
+                    Op::Sub => a.checked_sub(b).ok_or(ExprError::Overflow),
+                    Op::Mul => a.checked_mul(b).ok_or(ExprError::Overflow),
+                    Op::Div | Op::Rem if b == 0 => Err(ExprError::DivisionByZero),
+                    Op::Div => a.checked_div(b).ok_or(ExprError::Overflow),
+                    Op::Rem => a.checked_rem(b).ok_or(ExprError::Overflow),
+                    Op::Shl | Op::Shr if !(0..64).contains(&b) => Err(ExprError::InvalidShift(b)),
+                    // bits shifted out past the sign are lost
+                    Op::Shl if (a << b) >> b != a => Err(ExprError::Overflow),
+                    Op::Shl => Ok(a << b),
+                    Op::Shr => Ok(a >> b),
+                    Op::And => Ok(a & b),
+                    Op::Or => Ok(a | b),
+                    Op::Xor => Ok(a ^ b),
+                    Op::Not | Op::Hi | Op::Lo | Op::Hia => Ok(op.unary(b)),
+                }
+            }
+        }
+    }
+
+    /// replace the identifiers lookup knows with their values
+    pub fn substitute(self, lookup: &impl Fn(&str) -> Option<i64>) -> Expr {
+        match self {
+            Expr::Identifier(id) => match lookup(&id) {
+                Some(x) => Expr::Value(x),
+                None => Expr::Identifier(id),
+            },
+            Expr::Unary(op, operand) => Expr::Unary(op, Box::new(operand.substitute(lookup))),
+            Expr::Binary(op, lhs, rhs) => Expr::Binary(
+                op,
+                Box::new(lhs.substitute(lookup)),
+                Box::new(rhs.substitute(lookup)),
+            ),
+            expr => expr,
+        }
+    }
+
+    /// every identifier used in the expression, in the order they appear
+    pub fn identifiers(&self) -> Vec<&str> {
+        match self {
+            Expr::Value(_) => Vec::new(),
+            Expr::Identifier(id) => vec![id],
+            Expr::Unary(_, operand) => operand.identifiers(),
+            Expr::Binary(_, lhs, rhs) => {
+                let mut ids = lhs.identifiers();
+                ids.extend(rhs.identifiers());
+                ids
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(op: Op, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    #[test]
+    fn evaluation() {
+        let lookup = |id: &str| if id == "size" { Some(16) } else { None };
+
+        // (size * 4) - 1
+        let expr = binary(
+            Op::Sub,
+            binary(
+                Op::Mul,
+                Expr::Identifier("size".to_string()),
+                Expr::Value(4),
+            ),
+            Expr::Value(1),
+        );
+        assert_eq!(expr.evaluate(&lookup), Ok(63));
+
+        let expr = Expr::Unary(Op::Not, Box::new(Expr::Value(0)));
+        assert_eq!(expr.evaluate(&lookup), Ok(-1));
+
+        // %hia(x) and %lo(x) add up to x
+        let x = 0x1234_9abc;
+        let hia = Expr::Unary(Op::Hia, Box::new(Expr::Value(x)));
+        let lo = Expr::Unary(Op::Lo, Box::new(Expr::Value(x)));
+        assert_eq!(hia.evaluate(&lookup), Ok(0x1235));
+        assert_eq!(lo.evaluate(&lookup), Ok(-0x6544));
+        assert_eq!((0x1235 << 16) + -0x6544, x);
+
+        let expr = Expr::Unary(Op::Hi, Box::new(Expr::Value(x)));
+        assert_eq!(expr.evaluate(&lookup), Ok(0x1234));
+
+        let expr = binary(Op::Shr, Expr::Value(-16), Expr::Value(2));
+        assert_eq!(expr.evaluate(&lookup), Ok(-4));
+
+        let expr = binary(Op::Rem, Expr::Value(5), Expr::Value(0));
+        assert_eq!(expr.evaluate(&lookup), Err(ExprError::DivisionByZero));
+
+        let expr = binary(Op::Shl, Expr::Value(1), Expr::Value(64));
+        assert_eq!(expr.evaluate(&lookup), Err(ExprError::InvalidShift(64)));
+
+        let expr = binary(Op::Mul, Expr::Value(1 << 62), Expr::Value(2));
+        assert_eq!(expr.evaluate(&lookup), Err(ExprError::Overflow));
+
+        let expr = binary(Op::Shl, Expr::Value(3), Expr::Value(62));
+        assert_eq!(expr.evaluate(&lookup), Err(ExprError::Overflow));
+
+        let expr = binary(Op::Shl, Expr::Value(-1), Expr::Value(63));
+        assert_eq!(expr.evaluate(&lookup), Ok(i64::MIN));
+
+        let expr = Expr::Unary(Op::Sub, Box::new(Expr::Value(i64::MIN)));
+        assert_eq!(expr.evaluate(&lookup), Err(ExprError::Overflow));
+
+        let expr = binary(
+            Op::Add,
+            Expr::Identifier("size".to_string()),
+            Expr::Identifier("buffer".to_string()),
+        );
+        assert_eq!(
+            expr.evaluate(&lookup),
+            Err(ExprError::UnknownIdentifier("buffer".to_string()))
+        );
+        assert_eq!(expr.identifiers(), vec!["size", "buffer"]);
+        assert_eq!(
+            expr.substitute(&lookup),
+            binary(
+                Op::Add,
+                Expr::Value(16),
+                Expr::Identifier("buffer".to_string())
+            )
+        );
+    }
+}
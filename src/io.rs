@@ -1,31 +1,62 @@
-use std::{fs::File, io::prelude::*, path::Path, process};
+use crate::SourceFile;
+use std::{fs::File, io::prelude::*, path::Path};
 
-/// Print a binary (u32) buffer to a file specified in filename
-pub fn print_to_file(filename: &str, buffer: String) {
+/// Read an input file, naming it after its path
+pub fn read_source_file(filename: &str) -> Result<SourceFile, std::io::Error> {
+    let contents = std::fs::read_to_string(filename)?;
+
+    Ok(SourceFile::new(filename, &contents))
+}
+
+/// Print a text or binary buffer to a file specified in filename
+///
+/// if writing fails, the partially written file is deleted
+pub fn print_to_file(filename: &str, buffer: impl AsRef<[u8]>) -> Result<(), std::io::Error> {
     // create a file for the output (data)
     let file_path = Path::new(&filename);
 
-    let mut file_handle = match File::create(file_path) {
-        Err(why) => panic!("Couldn't create file: {}; {}", file_path.display(), why),
-        Ok(handle) => handle,
-    };
-
-    // save all the gatherred data to it
-    match file_handle.write_all(buffer.as_bytes()) {
-        Ok(_) => (),
-        Err(_) => file_cleanup(file_path),
-    };
-
-    // flush
-    match file_handle.flush() {
-        Ok(_) => (),
-        Err(_) => file_cleanup(file_path),
-    };
+    let mut file_handle = File::create(file_path)?;
+
+    // save all the gatherred data to it and flush
+    match file_handle
+        .write_all(buffer.as_ref())
+        .and_then(|_| file_handle.flush())
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            file_cleanup(file_path);
+            Err(e)
+        }
+    }
 }
 
 /// cleanup after an io error: delete the created file or display a message if unable
-fn file_cleanup(file: &Path) -> ! {
-    eprintln!("File write error: {}", file.display());
-    std::fs::remove_file(file).expect("File deletion failed. Delete output file and try again.");
-    process::exit(1);
+fn file_cleanup(file: &Path) {
+    if std::fs::remove_file(file).is_err() {
+        eprintln!(
+            "File deletion failed. Delete output file and try again: {}",
+            file.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_roundtrip() {
+        let path = std::env::temp_dir().join("greg_io_test.s");
+        let filename = path.to_str().unwrap();
+
+        print_to_file(filename, ".text\n").unwrap();
+        assert_eq!(
+            read_source_file(filename).unwrap(),
+            SourceFile::new(filename, ".text\n")
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(read_source_file(filename).is_err());
+        assert!(print_to_file("/nonexistent/greg/a.hex", String::new()).is_err());
+    }
 }
@@ -10,6 +10,21 @@ pub mod io;
 mod types;
 pub use types::*;
 
+/// handles source files and locations within them
+pub mod source;
+pub use source::{SourceFile, Span};
+
+/// handles expressions used as operands
+pub mod expr;
+pub use expr::{Expr, ExprError, Op};
+
+/// handles where the segments of a program are placed on the target
+pub mod memory;
+pub use memory::{MemoryMap, MemoryMapError};
+
+/// handles the startup code placed before every program
+pub mod crt0;
+
 /// handles parsing and lexing the program listing
 pub mod parser;
 pub use parser::ParserError;
@@ -20,6 +35,47 @@ pub use linker::LinkerError;
 
 /// handles converting the abstract program representation to binary data
 pub mod printer;
+pub use printer::Endianness;
+
+/// handles the table of symbols defined in a program and the map files made from it
+pub mod symbols;
+pub use symbols::{Segment, Symbol, SymbolTable};
+
+/// handles printing the source next to the words it assembled to
+pub mod listing;
+
+/// handles converting binary data back to a program listing
+pub mod disassembler;
+pub use disassembler::DisassemblerError;
+
+/// handles running assembled programs on the host
+pub mod sim;
+pub use sim::SimulatorError;
+
+/// # Assembler options
+///
+/// relax shortens la and ja to the fewest instructions that reach their address,
+/// turn it off for code that relies on their full length.
+///
+/// endianness selects the order bytes and halfwords are packed into words in
+///
+/// memory places the segments and remaps the data segment in the HEX output
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Options {
+    pub relax: bool,
+    pub endianness: Endianness,
+    pub memory: MemoryMap,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            relax: true,
+            endianness: Endianness::Little,
+            memory: MemoryMap::default(),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub enum AssemblerError {
@@ -36,35 +92,131 @@ impl std::fmt::Display for AssemblerError {
     }
 }
 
+impl AssemblerError {
+    /// location of the error in the source files, if it has one
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::ParserError(e) => e.span(),
+            Self::LinkerError(e) => Some(e.span()),
+        }
+    }
+
+    /// additional location related to the error, along with a description of it
+    pub fn note(&self) -> Option<(Span, &'static str)> {
+        match self {
+            Self::ParserError(_) => None,
+            Self::LinkerError(e) => e.note(),
+        }
+    }
+
+    /// format the error along with the file, line and column it occured on
+    /// and a snippet of the offending line, followed by the related note if there is one
+    pub fn render(&self, sources: &[SourceFile]) -> String {
+        let mut rendered = match self.span() {
+            Some(span) => source::render(sources, span, &self.to_string()),
+            None => self.to_string(),
+        };
+
+        if let Some((span, note)) = self.note() {
+            rendered.push('\n');
+            rendered.push_str(&source::render(sources, span, &format!("note: {note}")));
+        }
+
+        rendered
+    }
+}
+
 /// main function of the library - takes in a program listing, outputs an Intel HEX binary
 ///
-/// The program listing should be all the files of the program concatenated together,
-/// starting with the main function.
+/// All errors found in the listing are returned together. Default options are used,
+/// with the data segment remapped to the word address offset.
 ///
 /// # Panics:
 ///
 /// Should only panic if there is a bug.
-pub fn assemble(listing: &str, offset: u32) -> Result<String, AssemblerError> {
-    let mut tree = match parser::parse(listing) {
-        Ok(tree) => tree,
-        Err(e) => return Err(AssemblerError::ParserError(e)),
+pub fn assemble(listing: &str, offset: u32) -> Result<String, Vec<AssemblerError>> {
+    let options = Options {
+        memory: MemoryMap {
+            physical_data_offset: offset,
+            ..MemoryMap::default()
+        },
+        ..Options::default()
     };
+    assemble_sources(&[SourceFile::new("", listing)], &options)
+}
 
-    tree = match linker::link(tree) {
+/// assemble a program made up of several source files, outputs an Intel HEX binary
+///
+/// The files are read in order, as if they were concatenated.
+/// Spans of the returned errors index into sources.
+///
+/// # Panics:
+///
+/// Should only panic if there is a bug.
+pub fn assemble_sources(
+    sources: &[SourceFile],
+    options: &Options,
+) -> Result<String, Vec<AssemblerError>> {
+    let tree = link_sources(sources, options)?;
+
+    Ok(to_hex(tree, options))
+}
+
+/// convert a linked program tree to an Intel HEX binary,
+/// packed and placed in memory as options select
+pub fn to_hex(tree: ProgramTree, options: &Options) -> String {
+    let binary = printer::print_binary_with(tree, options.endianness, &options.memory);
+
+    printer::print_hex(binary, options.memory.physical_data_offset)
+}
+
+/// parse and link a program made up of several source files,
+/// outputs the program tree with all identifiers resolved
+///
+/// The files are read in order, as if they were concatenated.
+/// Spans of the returned errors index into sources.
+pub fn link_sources(
+    sources: &[SourceFile],
+    options: &Options,
+) -> Result<ProgramTree, Vec<AssemblerError>> {
+    let (mut tree, parser_errors) = parser::parse_with_recovery(sources, &options.memory);
+
+    if options.relax {
+        tree = linker::relax(tree);
+    }
+
+    let parsed = parser_errors.is_empty();
+    let mut errors: Vec<AssemblerError> = parser_errors
+        .into_iter()
+        .map(AssemblerError::ParserError)
+        .collect();
+
+    // the linker still checks the statements that could be parsed
+    let tree = match linker::link_with(tree, &options.memory) {
         Ok(tree) => tree,
-        Err(e) => return Err(AssemblerError::LinkerError(e)),
+        Err(linker_errors) => {
+            // identifiers may be defined on lines that failed to parse and addresses shift,
+            // so only redefinitions are reported unless the whole listing parsed
+            errors.extend(
+                linker_errors
+                    .into_iter()
+                    .filter(|e| parsed || matches!(e, LinkerError::RedefinedIdentifier(..)))
+                    .map(AssemblerError::LinkerError),
+            );
+            return Err(errors);
+        }
     };
 
-    let binary = printer::print_binary(tree);
-
-    let hex = printer::print_hex(binary, offset);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
 
-    Ok(hex)
+    Ok(tree)
 }
 
 #[cfg(test)]
 mod tests {
-    use parser::Loc;
+    use source::Span;
     use std::vec;
 
     use crate::*;
@@ -126,6 +278,39 @@ main:
             ],
         };
 
+        let sources = [SourceFile::new("", text)];
+        let options = Options {
+            relax: false,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            assemble_sources(&sources, &options).unwrap(),
+            printer::print_hex(binary, 0x400)
+        );
+
+        // ja and la shortened to the instructions their addresses need
+        let binary = ProgramBinary {
+            data: vec![125, 0, 0, 0, 0, 0, 0, 0, 0],
+            instructions: vec![
+                0xdd00_1000,
+                0xdf00_8000,
+                0xc100_0010,
+                0x7e10_0000,
+                0xda00_ffff,
+                0xc900_0007,
+                0x8090_0010,
+                0xc990_ffff,
+                0xf09a_0000,
+                0x8000_fff4,
+                0x4000_0000,
+                0xcff0_fffc,
+                0xf09f_0000,
+                0xe90f_0000,
+                0xcff0_0004,
+            ],
+        };
+
         assert_eq!(
             assemble(text, 0x400).unwrap(),
             printer::print_hex(binary, 0x400)
@@ -136,36 +321,878 @@ main:
     fn integration_test_errors() {
         assert_eq!(
             assemble("", 0x400),
-            Err(AssemblerError::ParserError(ParserError::Empty))
+            Err(vec![AssemblerError::ParserError(ParserError::Empty)])
         );
 
         assert_eq!(
-            assemble("&", 0x400),
-            Err(AssemblerError::ParserError(ParserError::InvalidToken(
-                Loc { row: 0, col: 0 }
-            )))
+            assemble("@", 0x400),
+            Err(vec![AssemblerError::ParserError(
+                ParserError::InvalidToken(Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 1
+                })
+            )])
         );
 
         assert_eq!(
             assemble(".text\naddi $t1, 0", 0x400),
-            Err(AssemblerError::ParserError(ParserError::Incomplete(Loc {
-                row: 1,
-                col: 10
-            })))
+            Err(vec![AssemblerError::ParserError(ParserError::Incomplete(
+                Span {
+                    file: 0,
+                    row: 1,
+                    col: 10,
+                    len: 1
+                }
+            ))])
         );
 
         assert_eq!(
             assemble(".text main:\nla $t1, id", 0x400),
-            Err(AssemblerError::LinkerError(LinkerError::UnknownIdentifier(
-                "id".to_string()
-            )))
+            Err(vec![AssemblerError::LinkerError(
+                LinkerError::UnknownIdentifier(
+                    "id".to_string(),
+                    Span {
+                        file: 0,
+                        row: 1,
+                        col: 0,
+                        len: 10
+                    }
+                )
+            )])
         );
 
         assert_eq!(
             assemble("la $t1, id", 0x400),
-            Err(AssemblerError::ParserError(
-                ParserError::CodeOutsideSegment(Loc { row: 0, col: 0 })
+            Err(vec![AssemblerError::ParserError(
+                ParserError::CodeOutsideSegment(Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 2
+                })
+            )])
+        );
+    }
+
+    #[test]
+    fn integration_test_multiple_errors() {
+        let text = "
+.data
+    a: .word 1
+    a: .word 2
+.text
+main:
+    addi $t0, $t0 1
+    add $t0, $t0, @
+    beq $t0, $zero, nowhere
+    sw $t0 lw $t1, $t2
+    add $t0, $t0, $t0
+        ";
+
+        assert_eq!(
+            assemble(text, 0x400),
+            Err(vec![
+                AssemblerError::ParserError(ParserError::Incomplete(Span {
+                    file: 0,
+                    row: 6,
+                    col: 18,
+                    len: 1
+                })),
+                AssemblerError::ParserError(ParserError::InvalidToken(Span {
+                    file: 0,
+                    row: 7,
+                    col: 18,
+                    len: 1
+                })),
+                AssemblerError::ParserError(ParserError::Incomplete(Span {
+                    file: 0,
+                    row: 9,
+                    col: 11,
+                    len: 2
+                })),
+                AssemblerError::LinkerError(LinkerError::RedefinedIdentifier(
+                    "a".to_string(),
+                    Span {
+                        file: 0,
+                        row: 3,
+                        col: 4,
+                        len: 1
+                    },
+                    Span {
+                        file: 0,
+                        row: 2,
+                        col: 4,
+                        len: 1
+                    }
+                )),
+            ])
+        );
+
+        // without parser errors, unknown identifiers are reported as well
+        let text = "
+.data
+    a: .word 1
+    a: .word 2
+.text
+main:
+    beq $t0, $zero, nowhere
+        ";
+
+        assert_eq!(
+            assemble(text, 0x400),
+            Err(vec![
+                AssemblerError::LinkerError(LinkerError::RedefinedIdentifier(
+                    "a".to_string(),
+                    Span {
+                        file: 0,
+                        row: 3,
+                        col: 4,
+                        len: 1
+                    },
+                    Span {
+                        file: 0,
+                        row: 2,
+                        col: 4,
+                        len: 1
+                    }
+                )),
+                AssemblerError::LinkerError(LinkerError::UnknownIdentifier(
+                    "nowhere".to_string(),
+                    Span {
+                        file: 0,
+                        row: 6,
+                        col: 4,
+                        len: 23
+                    }
+                )),
+            ])
+        );
+    }
+
+    #[test]
+    fn integration_test_source_files() {
+        let sources = vec![
+            SourceFile::new("main.s", ".text\nmain:\n    ja $ra, func\n"),
+            SourceFile::new("func.s", "func:\n    addi $t0, $t0 1\n    la $t1, main\n"),
+        ];
+
+        let errors = assemble_sources(&sources, &Options::default()).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![AssemblerError::ParserError(ParserError::Incomplete(Span {
+                file: 1,
+                row: 1,
+                col: 18,
+                len: 1
+            }))]
+        );
+
+        assert_eq!(
+            errors[0].render(&sources),
+            "Error: Invalid statement\n \
+             --> func.s:2:19\n  \
+             |\n\
+             2 |     addi $t0, $t0 1\n  \
+             |                   ^"
+        );
+    }
+
+    // tests if constants assemble the same as the values they stand for
+    #[test]
+    fn integration_test_constants() {
+        let with_constants = "
+.equ IO_BASE, 0xffff0000
+.equ COUNT, 4
+.data
+    buffer: .space COUNT
+    size: .word COUNT
+    display: .addr IO_BASE
+.text
+.set step, 1
+main:
+    addi $t0, $zero, COUNT
+    lui $t1, 0xffff
+    la $t2, IO_BASE
+    beq $t0, $zero, SKIP
+.set step, 2
+    addi $t0, $t0, step
+    ja $ra, END
+.equ SKIP, 8
+.equ END, 0x40
+";
+
+        let with_values = "
+.data
+    buffer: .space 4
+    size: .word 4
+    display: .addr 0xffff0000
+.text
+main:
+    addi $t0, $zero, 4
+    lui $t1, 0xffff
+    la $t2, 0xffff0000
+    beq $t0, $zero, 8
+    addi $t0, $t0, 2
+    ja $ra, 0x40
+";
+
+        assert_eq!(
+            assemble(with_constants, 0x400),
+            assemble(with_values, 0x400)
+        );
+
+        let errors = assemble(
+            ".equ A, 1\n.data\n.set A, 2\nA: .space B\n.equ B, 1\n",
+            0x400,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                AssemblerError::ParserError(ParserError::UndefinedConstant(
+                    "B".to_string(),
+                    Span {
+                        file: 0,
+                        row: 3,
+                        col: 10,
+                        len: 1
+                    }
+                )),
+                AssemblerError::LinkerError(LinkerError::RedefinedIdentifier(
+                    "A".to_string(),
+                    Span {
+                        file: 0,
+                        row: 2,
+                        col: 0,
+                        len: 9
+                    },
+                    Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    }
+                )),
+            ]
+        );
+    }
+
+    // tests if expressions assemble the same as the values they evaluate to
+    #[test]
+    fn integration_test_expressions() {
+        let with_expressions = "
+.equ SIZE, 4
+.data
+    buffer: .space SIZE * 2
+    last: .word buffer + (SIZE*2 - 1) * 4
+    length: .word end - main
+    mask: .word ~(1 << 3) & 0xff
+.text
+main:
+    addi $t0, $zero, (SIZE*4)-1
+    la $t1, buffer + 8
+    beq $t0, $zero, end
+    sl $t0, $t0, SIZE / 2
+end:
+    add $t0, $t0, $t0
+";
+
+        let with_values = "
+.data
+    buffer: .space 8
+    last: .word 0x1000001c
+    length: .word 0x14
+    mask: .word 0xf7
+.text
+main:
+    addi $t0, $zero, 15
+    la $t1, 0x10000008
+    beq $t0, $zero, 8
+    sl $t0, $t0, 2
+    add $t0, $t0, $t0
+";
+
+        assert_eq!(
+            assemble(with_expressions, 0x400),
+            assemble(with_values, 0x400)
+        );
+
+        let errors = assemble(
+            ".text\nmain:\n    addi $t0, $zero, main / 0\n    beq $t0, $zero, main + 0x10000\n",
+            0x400,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                AssemblerError::LinkerError(LinkerError::InvalidExpression(
+                    ExprError::DivisionByZero,
+                    Span {
+                        file: 0,
+                        row: 2,
+                        col: 4,
+                        len: 25
+                    }
+                )),
+                AssemblerError::LinkerError(LinkerError::ImmediateOutOfRange(
+                    0xfffc,
+                    -0x8000..=0x7fff,
+                    Span {
+                        file: 0,
+                        row: 3,
+                        col: 4,
+                        len: 30
+                    }
+                )),
+            ]
+        );
+
+        let errors = assemble(
+            ".text\nmain:\n    addi $t0, $zero, main + 0x7fffffffffffffff + 1\n",
+            0x400,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![AssemblerError::LinkerError(LinkerError::InvalidExpression(
+                ExprError::Overflow,
+                Span {
+                    file: 0,
+                    row: 2,
+                    col: 4,
+                    len: 46
+                }
+            ))]
+        );
+    }
+
+    // tests if strings, bytes and halfwords assemble to the words they are packed into
+    #[test]
+    fn integration_test_strings() {
+        let with_strings = "
+.equ NEWLINE, 10
+.data
+    message: .asciiz \"Hi!\\n\"
+    bytes: .byte 1, 2, 3, -1, NEWLINE
+    halves: .half 0x1234, -2
+    after: .word message
+.text
+main:
+    la $t0, after
+";
+
+        let with_words = "
+.data
+    message: .word 0x0a216948
+    .word 0
+    bytes: .word 0xff030201
+    .word 10
+    halves: .word 0xfffe1234
+    after: .word 0x10000000
+.text
+main:
+    la $t0, 0x10000014
+";
+
+        assert_eq!(assemble(with_strings, 0x400), assemble(with_words, 0x400));
+
+        let sources = [SourceFile::new("", with_strings)];
+        let options = Options {
+            endianness: Endianness::Big,
+            ..Options::default()
+        };
+        let binary = printer::print_binary_with(
+            link_sources(&sources, &options).unwrap(),
+            Endianness::Big,
+            &MemoryMap::default(),
+        );
+
+        assert_eq!(
+            binary.data,
+            vec![
+                0x4869_210a,
+                0,
+                0x0102_03ff,
+                0x0a00_0000,
+                0x1234_fffe,
+                0x1000_0000
+            ]
+        );
+
+        assert_eq!(
+            assemble(".data\n    .ascii \"\\q\"\n", 0x400),
+            Err(vec![AssemblerError::ParserError(
+                ParserError::InvalidToken(Span {
+                    file: 0,
+                    row: 1,
+                    col: 11,
+                    len: 4
+                })
+            )])
+        );
+    }
+
+    // tests if word lists assemble the same as one word per line
+    #[test]
+    fn integration_test_word_lists() {
+        let with_lists = "
+.equ ENTRIES, 3
+.data
+    table: .word main, second, second + 4
+    fill: .word 0xdead : ENTRIES, 0 : 2, table
+.text
+main:
+    la $t0, table
+second:
+    add $t0, $t0, $t0
+    add $t0, $t0, $t0
+";
+
+        let with_words = "
+.data
+    table: .word 0
+    .word 4
+    .word 8
+    fill: .word 0xdead
+    .word 0xdead
+    .word 0xdead
+    .space 2
+    .word 0x10000000
+.text
+main:
+    lui $t0, 0x1000
+    add $t0, $t0, $t0
+    add $t0, $t0, $t0
+";
+
+        assert_eq!(assemble(with_lists, 0x400), assemble(with_words, 0x400));
+    }
+
+    // tests if segments are placed and bounded by the memory map
+    #[test]
+    fn integration_test_memory_map() {
+        let options = Options {
+            memory: MemoryMap {
+                text_base: 0x100,
+                text_size: 0x10,
+                data_base: 0x2000,
+                data_size: 0x8,
+                stack_top: 0x3000,
+                physical_data_offset: 0x40,
+                text_depth: None,
+                data_depth: None,
+            },
+            ..Options::default()
+        };
+        let sources = |listing| [SourceFile::new("", listing)];
+
+        let listing = ".data\n    value: .word 1\n.text\nmain:\n    la $t0, value\n";
+        let tree = link_sources(&sources(listing), &options).unwrap();
+        assert_eq!(tree.instructions[0].address, 0x100);
+        assert_eq!(tree.instructions[0].imm, 0x2000);
+        assert_eq!(tree.data[0].address, 0x2000);
+        assert_eq!(
+            assemble_sources(&sources(listing), &options).unwrap(),
+            ":04000000C900200013\n:0400400000000001BB\n:00000001FF\n"
+        );
+
+        let listing = ".data\n    .space 3\n.text\nmain:\n    la $t0, 0x12345678\n    la $t0, 0x12345678\n    la $t0, 0x12345678\n";
+        let span = |row, len| Span {
+            file: 0,
+            row,
+            col: 4,
+            len,
+        };
+        assert_eq!(
+            assemble_sources(&sources(listing), &options).unwrap_err(),
+            vec![
+                AssemblerError::LinkerError(LinkerError::SegmentOverflow("data", 0x8, span(1, 8))),
+                AssemblerError::LinkerError(LinkerError::SegmentOverflow(
+                    "text",
+                    0x10,
+                    span(6, 18)
+                )),
+            ]
+        );
+
+        // code running into the end of the address space
+        let options = Options {
+            memory: MemoryMap {
+                text_base: 0xf000_0000,
+                text_size: 0x1000_0000,
+                ..options.memory
+            },
+            ..options
+        };
+        let listing = ".text\n    .org 0xfffffff8\nmain:\n    la $t0, main\n";
+        assert_eq!(
+            assemble_sources(&sources(listing), &options).unwrap_err(),
+            vec![AssemblerError::ParserError(ParserError::AddressOverflow(
+                span(3, 12)
+            ))]
+        );
+
+        // segments can be filled right up to the end of the address space
+        let listing = ".data\n    .space 1\n    last: .word 7\n.text\nmain:\n    lw $t0, $t1\n    add $t0, $t0, $t0\n";
+        for (text_base, data_base) in [(0xffff_fff8, 0x1000), (0x1000, 0xffff_fff8)] {
+            let options = Options {
+                memory: MemoryMap {
+                    text_base,
+                    text_size: 0x8,
+                    data_base,
+                    data_size: 0x8,
+                    ..options.memory
+                },
+                ..options
+            };
+            let tree = link_sources(&sources(listing), &options).unwrap();
+            assert_eq!(tree.data[1].address, data_base + 4);
+            assert_eq!(tree.instructions[1].address, text_base + 4);
+            assert!(assemble_sources(&sources(listing), &options).is_ok());
+
+            let table = linker::symbol_table(&tree);
+            assert_eq!(table.iter().find(|s| s.name == "last").unwrap().size, 4);
+            assert_eq!(table.iter().find(|s| s.name == "main").unwrap().size, 8);
+            let elf = printer::print_elf(&tree, &table, None, options.endianness, &options.memory);
+            assert!(!elf.is_empty());
+        }
+    }
+
+    // tests if .align and .org leave gaps that are filled with zeros
+    #[test]
+    fn integration_test_placement() {
+        let with_placement = "
+.data
+    first: .word 1
+    .align 4
+    second: .word 2
+    .org 0x10000040
+    third: .word third
+.text
+main:
+    la $t0, handler
+    .org 0x10
+handler:
+    add $t0, $t0, $t0
+";
+
+        let with_padding = "
+.data
+    first: .word 1
+    .space 3
+    second: .word 2
+    .space 11
+    third: .word 0x10000040
+.text
+main:
+    addi $t0, $zero, 0x10
+    and $zero, $zero, $zero
+    and $zero, $zero, $zero
+    and $zero, $zero, $zero
+handler:
+    add $t0, $t0, $t0
+";
+
+        assert_eq!(
+            assemble(with_placement, 0x400),
+            assemble(with_padding, 0x400)
+        );
+
+        let errors = assemble(
+            ".text\nmain:\n    add $t0, $t0, $t0\n    .org 0\n    add $t0, $t0, $t0\n",
+            0x400,
+        )
+        .unwrap_err();
+
+        let span = |row| Span {
+            file: 0,
+            row,
+            col: 4,
+            len: 17,
+        };
+        assert_eq!(
+            errors,
+            vec![AssemblerError::LinkerError(
+                LinkerError::OverlappingRegions(span(4), span(2))
+            )]
+        );
+
+        // a label would be ambiguous between the addresses before and after the directive
+        for placement in [".org 0x10", ".align 4"] {
+            let listing = format!(".text\nmain:\n    {placement}\n    add $t0, $t0, $t0\n");
+            assert_eq!(
+                assemble(&listing, 0x400).unwrap_err(),
+                vec![AssemblerError::ParserError(ParserError::LabelledDirective(
+                    Span {
+                        file: 0,
+                        row: 1,
+                        col: 0,
+                        len: 4
+                    }
+                ))]
+            );
+        }
+    }
+
+    // tests if code placed right where it already was stays there once relaxation shortens la
+    #[test]
+    fn integration_test_relaxed_placement() {
+        let code = |placement| {
+            format!(
+                "
+.text
+main:
+    la $t0, main
+    addi $t0, $t0, 1
+    addi $t0, $t0, 1
+    addi $t0, $t0, 1
+    {placement}
+vector:
+    addi $t1, $zero, 1
+"
+            )
+        };
+
+        let with_padding = "
+.text
+main:
+    addi $t0, $zero, 0
+    addi $t0, $t0, 1
+    addi $t0, $t0, 1
+    addi $t0, $t0, 1
+    and $zero, $zero, $zero
+    and $zero, $zero, $zero
+    and $zero, $zero, $zero
+    and $zero, $zero, $zero
+vector:
+    addi $t1, $zero, 1
+";
+
+        for placement in [".org 0x20", ".align 5"] {
+            assert_eq!(
+                assemble(&code(placement), 0x400),
+                assemble(with_padding, 0x400)
+            );
+        }
+    }
+
+    // tests if relocation operators load the halves of an address
+    #[test]
+    fn integration_test_relocation() {
+        let with_relocation = "
+.data
+    padding: .space 0x2000
+    buffer: .word 1
+.text
+main:
+    lui $t0, %hia(buffer)
+    addi $t0, $t0, %lo(buffer)
+    lui $t1, %hi(buffer + 4)
+    addi $t2, $zero, %lo(0x1234)
+";
+
+        let with_values = "
+.data
+    padding: .space 0x2000
+    buffer: .word 1
+.text
+main:
+    lui $t0, 0x1001
+    addi $t0, $t0, -0x8000
+    lui $t1, 0x1000
+    addi $t2, $zero, 0x1234
+";
+
+        assert_eq!(
+            assemble(with_relocation, 0x400),
+            assemble(with_values, 0x400)
+        );
+
+        let errors = assemble(".text\nmain:\n    addi $t0, $zero, %lo main\n", 0x400).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![AssemblerError::ParserError(ParserError::Incomplete(Span {
+                file: 0,
+                row: 2,
+                col: 25,
+                len: 4
+            }))]
+        );
+    }
+
+    #[test]
+    fn integration_test_redefinition_note() {
+        let sources = vec![
+            SourceFile::new("main.s", ".text\nmain:\n    ja $ra, func\n"),
+            SourceFile::new("func.s", "main:\n    j $zero, $ra\n"),
+        ];
+
+        let errors = assemble_sources(&sources, &Options::default()).unwrap_err();
+
+        assert_eq!(
+            errors[0].render(&sources),
+            "Error: Identifier defined more than once: \"main\"\n \
+             --> func.s:1:1\n  \
+             |\n\
+             1 | main:\n  \
+             | ^^^^\n\
+             note: first defined here\n \
+             --> main.s:2:1\n  \
+             |\n\
+             2 | main:\n  \
+             | ^^^^"
+        );
+
+        assert_eq!(
+            errors[1],
+            AssemblerError::LinkerError(LinkerError::UnknownIdentifier(
+                "func".to_string(),
+                Span {
+                    file: 0,
+                    row: 2,
+                    col: 4,
+                    len: 12
+                }
             ))
         );
     }
+
+    #[test]
+    fn integration_test_literal_overflow() {
+        let errors =
+            assemble(".text\nmain:\n    addi $t0, $t0, 99999999999999999999\n", 0).unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![AssemblerError::ParserError(ParserError::LiteralOverflow(
+                Span {
+                    file: 0,
+                    row: 2,
+                    col: 19,
+                    len: 20
+                }
+            ))]
+        );
+    }
+
+    // feeds pseudo-random listings built from assembler fragments through every stage,
+    // none of which may panic, whatever the input
+    #[test]
+    fn integration_test_fuzz() {
+        // deeply nested expressions, around the nesting limit and past it
+        let nested = [
+            "(".repeat(255),
+            ")".repeat(255),
+            "(".repeat(1000),
+            "-".repeat(300),
+            "~-".repeat(300),
+            "1+".repeat(300),
+            "%hi(".repeat(300),
+        ];
+
+        // numbers are kept small where they place data, so the binaries stay small
+        let fragments = [
+            ".data",
+            ".text",
+            ".word",
+            ".space",
+            ".addr",
+            ".equ",
+            ".set",
+            ".org",
+            ".align",
+            ".ascii",
+            ".asciiz",
+            ".byte",
+            ".half",
+            "addi",
+            "la",
+            "ja",
+            "push",
+            "pop",
+            "beq",
+            "lui",
+            "sl",
+            "$t0",
+            "$zero",
+            "$99",
+            ",",
+            ":",
+            "#",
+            "\n",
+            "\t",
+            " ",
+            "label",
+            "main",
+            "0x",
+            "0xffffffffffffffffff",
+            "0x7fffffff",
+            "99999999999999999999",
+            "4294967296",
+            "-",
+            "+",
+            "_",
+            "1_0",
+            "-_",
+            "&",
+            "𝝀",
+            "é",
+            "\r\n",
+            "-9223372036854775808",
+            "2",
+            "4",
+            "0x40",
+            "%hi",
+            "%lo",
+            "%hia",
+            "(",
+            ")",
+            "~",
+            "|",
+            "^",
+            "%",
+            "\"",
+            "\"text\"",
+            "\"\\n\\0\\\"\"",
+            "\\",
+            "'",
+        ];
+
+        // xorshift, so the test is deterministic
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..5000 {
+            let mut listing = String::new();
+            for _ in 0..random() % 40 {
+                let r = random();
+                if r % 8 == 0 {
+                    listing.push(char::from_u32((r >> 8) as u32 % 0x3000).unwrap_or('?'));
+                } else if r % 40 == 1 {
+                    listing.push_str(&nested[(r >> 8) as usize % nested.len()]);
+                } else {
+                    listing.push_str(fragments[(r >> 8) as usize % fragments.len()]);
+                }
+                if r % 3 == 0 {
+                    listing.push(' ');
+                }
+            }
+
+            for _ in parser::mylexer::Lexer::new(&listing) {}
+            let _ = parser::parse(&listing);
+            let _ = assemble(&listing, 0x400);
+        }
+    }
 }
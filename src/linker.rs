@@ -1,92 +1,648 @@
+use crate::expr::{Expr, ExprError, Op};
+use crate::symbols::{Segment, Symbol as TableSymbol, SymbolTable};
 use crate::types::*;
+use crate::MemoryMap;
 use crate::ProgramTree;
+use crate::Span;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
+/// # Linker errors
+///
+/// UnknownIdentifier holds the location of the statement using the identifier,
+/// EntryNotFound holds the entry symbol the startup code calls and the location of the call,
+/// RedefinedIdentifier holds the location of the redefinition followed by that of the first definition,
+/// ImmediateOutOfRange holds the resolved value and the range it has to fit in,
+/// InvalidExpression holds the reason an expression could not be evaluated
 #[derive(Debug, PartialEq)]
 pub enum LinkerError {
-    UnknownIdentifier(String),
-    RedefinedIdentifier(String),
+    UnknownIdentifier(String, Span),
+    EntryNotFound(String, Span),
+    RedefinedIdentifier(String, Span, Span),
+    ImmediateOutOfRange(i64, RangeInclusive<i64>, Span),
+    InvalidExpression(ExprError, Span),
+    OverlappingRegions(Span, Span),
+    SegmentOverflow(&'static str, u32, Span),
+}
+
+impl LinkerError {
+    /// location of the statement that caused the error
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnknownIdentifier(_, span)
+            | Self::EntryNotFound(_, span)
+            | Self::RedefinedIdentifier(_, span, _)
+            | Self::ImmediateOutOfRange(_, _, span)
+            | Self::InvalidExpression(_, span)
+            | Self::OverlappingRegions(span, _)
+            | Self::SegmentOverflow(_, _, span) => *span,
+        }
+    }
+
+    /// error for an expression that could not be evaluated at span
+    fn from_expr(e: ExprError, span: Span) -> Self {
+        match e {
+            ExprError::UnknownIdentifier(id) => Self::UnknownIdentifier(id, span),
+            e => Self::InvalidExpression(e, span),
+        }
+    }
+
+    /// additional location related to the error, along with a description of it
+    pub fn note(&self) -> Option<(Span, &'static str)> {
+        match self {
+            Self::RedefinedIdentifier(_, _, first) => Some((*first, "first defined here")),
+            Self::OverlappingRegions(_, other) => Some((*other, "overlaps with this")),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for LinkerError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Self::UnknownIdentifier(id) if *id == *"main" => write!(f, "Main function not found"),
-            Self::UnknownIdentifier(id) => write!(f, "Identifier not recognized: \"{id}\""),
-            Self::RedefinedIdentifier(id) => {
+            Self::UnknownIdentifier(id, _) => write!(f, "Identifier not recognized: \"{id}\""),
+            Self::EntryNotFound(id, _) => write!(f, "Entry symbol not found: \"{id}\""),
+            Self::RedefinedIdentifier(id, _, _) => {
                 write!(f, "Identifier defined more than once: \"{id}\"")
             }
+            Self::ImmediateOutOfRange(imm, range, _) => write!(
+                f,
+                "Immediate out of range: {imm} does not lie between {} and {}",
+                range.start(),
+                range.end()
+            ),
+            Self::InvalidExpression(e, _) => write!(f, "{e}"),
+            Self::OverlappingRegions(_, _) => write!(f, "Memory regions overlap"),
+            Self::SegmentOverflow(segment, size, _) => write!(
+                f,
+                "Segment overflow: .{segment} does not fit in its 0x{size:X} bytes"
+            ),
         }
     }
 }
 
+/// # Symbol
+///
+/// what an identifier was defined as: the address of a label or the value of a constant
+struct Symbol {
+    value: i64,
+    span: Span,
+    kind: SymbolKind,
+}
+
+#[derive(PartialEq)]
+enum SymbolKind {
+    Label,
+    Constant,
+    RedefinableConstant,
+}
+
 /// links all identifiers in the program together,
 /// converting them to immediates of their instructions
-pub fn link(mut tree: ProgramTree) -> Result<ProgramTree, LinkerError> {
+///
+/// constants are used as if their value was written in place of the identifier
+///
+/// all identifiers are checked, so every error in the program is returned.
+/// Segments have to fit the default memory map
+pub fn link(tree: ProgramTree) -> Result<ProgramTree, Vec<LinkerError>> {
+    link_with(tree, &MemoryMap::default())
+}
+
+/// links all identifiers in the program together,
+/// checking that every segment fits in the size memory gives it
+pub fn link_with(
+    mut tree: ProgramTree,
+    memory: &MemoryMap,
+) -> Result<ProgramTree, Vec<LinkerError>> {
     // linking is performed using a hash map
+    let (map, mut errors) = symbols(&tree);
+
+    // code and data placed with .org must not overlap what is already there
+    errors.extend(overlaps(&tree));
+    errors.extend(overflows(&tree, memory));
+
+    // then, every expression left for the linker is evaluated using the map.
+    // If an identifier is not found or the expression can not be evaluated, an error is recorded
+    let lookup = |id: &str| map.get(id).map(|symbol| symbol.value);
+
+    for instrnode in &mut tree.instructions {
+        // skip everything that was already resolved by the parser
+        let Some(expr) = &instrnode.imm_expr else {
+            continue;
+        };
+
+        let value = match expr.evaluate(&lookup) {
+            Ok(value) => value,
+            Err(e) => {
+                let e = LinkerError::from_expr(e, instrnode.span);
+                // pseudoinstructions expand to several nodes, report them only once
+                if errors.last() != Some(&e) {
+                    errors.push(e);
+                }
+                continue;
+            }
+        };
+
+        instrnode.imm = match instrnode.op {
+            // beq, bne require an offset if they branch to a label
+            Instr::Beq | Instr::Bne if uses_label(expr, &map) => value - instrnode.address as i64,
+            _ => value,
+        };
+
+        // the result has to fit in the immediate field
+        let range = instrnode.op.imm_range();
+        if !range.contains(&instrnode.imm) {
+            errors.push(LinkerError::ImmediateOutOfRange(
+                instrnode.imm,
+                range,
+                instrnode.span,
+            ));
+        }
+    }
+
+    for datanode in &mut tree.data {
+        let Some(expr) = &datanode.data_expr else {
+            continue;
+        };
+
+        match expr.evaluate(&lookup) {
+            Ok(value) if WORD_RANGE.contains(&value) => datanode.data = value,
+            Ok(value) => errors.push(LinkerError::ImmediateOutOfRange(
+                value,
+                WORD_RANGE,
+                datanode.span,
+            )),
+            Err(e) => errors.push(LinkerError::from_expr(e, datanode.span)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(tree)
+    } else {
+        Err(errors)
+    }
+}
+
+/// gather every identifier definition in the program into a map,
+/// along with the errors found while doing so
+fn symbols(tree: &ProgramTree) -> (HashMap<String, Symbol>, Vec<LinkerError>) {
     let mut map = HashMap::new();
+    let mut errors = Vec::new();
+
+    // gather all definitions, in the order they appear in the listing,
+    // so redefinitions are reported at the later one
+    let labels = tree
+        .data
+        .iter()
+        .map(|n| {
+            (
+                &n.identifier,
+                n.address as i64,
+                n.label_span,
+                SymbolKind::Label,
+            )
+        })
+        .chain(tree.instructions.iter().map(|n| {
+            (
+                &n.identifier,
+                n.address as i64,
+                n.label_span,
+                SymbolKind::Label,
+            )
+        }));
+    let constants = tree.constants.iter().map(|n| {
+        let kind = if n.redefinable {
+            SymbolKind::RedefinableConstant
+        } else {
+            SymbolKind::Constant
+        };
+        (&n.identifier, n.value, n.span, kind)
+    });
+    let mut definitions: Vec<_> = labels.chain(constants).collect();
+    definitions.sort_by_key(|(_, _, span, _)| *span);
 
     // if an identifier definition is encountered, it is added to the map
-    for datanode in &tree.data {
-        if datanode.identifier != *"" {
-            // return error if the key was already present
-            match map.insert(datanode.identifier.clone(), datanode.address) {
-                None => (),
-                Some(_) => {
-                    return Err(LinkerError::RedefinedIdentifier(
-                        datanode.identifier.clone(),
-                    ))
+    for (identifier, value, span, kind) in definitions {
+        let symbol = Symbol { value, span, kind };
+        if let Err(e) = define(&mut map, identifier, symbol) {
+            errors.push(e);
+        }
+    }
+
+    (map, errors)
+}
+
+/// find the regions of memory that are used more than once,
+/// reporting each pair of overlapping statements once at the later one
+fn overlaps(tree: &ProgramTree) -> Vec<LinkerError> {
+    // memory mapped addr nodes and empty nodes take up no memory
+    let data = tree
+        .data
+        .iter()
+        .filter(|n| n.block != Bl::Addr && n.num > 0)
+        .map(|n| {
+            (
+                n.address as u64,
+                n.address as u64 + 4 * n.num as u64,
+                n.span,
+            )
+        });
+    let instructions = tree
+        .instructions
+        .iter()
+        .map(|n| (n.address as u64, n.address as u64 + 4, n.span));
+
+    let mut errors = Vec::new();
+    for regions in [data.collect::<Vec<_>>(), instructions.collect()] {
+        let mut regions = regions;
+        regions.sort_by_key(|&(start, _, span)| (start, span));
+
+        // the region reaching furthest so far, anything starting before its end overlaps it
+        let mut furthest: Option<(u64, Span)> = None;
+        for (start, end, span) in regions {
+            match furthest {
+                Some((reach, other)) if start < reach && other != span => {
+                    let (later, earlier) = if span > other {
+                        (span, other)
+                    } else {
+                        (other, span)
+                    };
+                    let e = LinkerError::OverlappingRegions(later, earlier);
+                    if !errors.contains(&e) {
+                        errors.push(e);
+                    }
                 }
+                _ => (),
+            }
+            if furthest.is_none_or(|(reach, _)| end > reach) {
+                furthest = Some((end, span));
             }
         }
     }
-    for instrnode in &tree.instructions {
-        if instrnode.identifier != *"" {
-            match map.insert(instrnode.identifier.clone(), instrnode.address) {
-                None => (),
-                Some(_) => {
-                    return Err(LinkerError::RedefinedIdentifier(
-                        instrnode.identifier.clone(),
-                    ))
+
+    errors
+}
+
+/// gather every identifier defined in a linked program into a symbol table, sorted by value
+///
+/// a label covers everything up to the next label or gap in its segment,
+/// constants defined with .set keep their last value
+pub fn symbol_table(tree: &ProgramTree) -> SymbolTable {
+    let (map, _) = symbols(tree);
+    let mut table = SymbolTable::new();
+
+    // sizes are found by walking the nodes up to the next label
+    let data: Vec<_> = tree
+        .data
+        .iter()
+        .map(|n| {
+            let size = if n.block == Bl::Addr { 4 } else { 4 * n.num };
+            (
+                &n.identifier,
+                n.address,
+                size,
+                n.label_span,
+                n.block == Bl::Addr,
+            )
+        })
+        .collect();
+    let instructions: Vec<_> = tree
+        .instructions
+        .iter()
+        .map(|n| (&n.identifier, n.address, 4, n.label_span, false))
+        .collect();
+
+    for (nodes, segment) in [(data, Segment::Data), (instructions, Segment::Text)] {
+        for (i, &(identifier, address, size, span, absolute)) in nodes.iter().enumerate() {
+            if identifier.is_empty() {
+                continue;
+            }
+            // a redefined label is only in the table where it was first defined
+            if map
+                .get(identifier)
+                .is_some_and(|symbol| symbol.span != span)
+            {
+                continue;
+            }
+
+            // the last word can end right at the end of the address space
+            let mut end = address as u64 + size as u64;
+            if !absolute {
+                for &(next, next_address, next_size, _, next_absolute) in &nodes[i + 1..] {
+                    if !next.is_empty() || next_absolute || next_address as u64 != end {
+                        break;
+                    }
+                    end += next_size as u64;
                 }
             }
+
+            table.push(TableSymbol {
+                name: identifier.clone(),
+                value: address as i64,
+                segment: if absolute { Segment::Absolute } else { segment },
+                size: (end - address as u64) as u32,
+                file: span.file,
+            });
         }
     }
 
-    // then, for each located use of an identifier, the map is checked for valid definitions.
-    // If one is not found, the function returns err
-    for instrnode in &mut tree.instructions {
-        // skip everything that does not use an identifier
-        if instrnode.imm_identifier != *"" {
-            // read out the address the identifier was defined for
-            let target_address = match map.get(&instrnode.imm_identifier) {
-                Some(n) => n.to_owned(),
-                None => {
-                    return Err(LinkerError::UnknownIdentifier(
-                        instrnode.imm_identifier.clone(),
-                    ))
-                }
+    for (name, symbol) in &map {
+        if symbol.kind != SymbolKind::Label {
+            table.push(TableSymbol {
+                name: name.clone(),
+                value: symbol.value,
+                segment: Segment::Absolute,
+                size: 0,
+                file: symbol.span.file,
+            });
+        }
+    }
+
+    table.sort_by(|a, b| (a.value, &a.name).cmp(&(b.value, &b.name)));
+    table
+}
+
+/// find the first statement of each segment that reaches past its end
+fn overflows(tree: &ProgramTree, memory: &MemoryMap) -> Vec<LinkerError> {
+    let data = tree
+        .data
+        .iter()
+        .filter(|n| n.block != Bl::Addr)
+        .find(|n| n.address as u64 + 4 * n.num as u64 > memory.data().end)
+        .map(|n| LinkerError::SegmentOverflow("data", memory.data_size, n.span));
+    let text = tree
+        .instructions
+        .iter()
+        .find(|n| n.address as u64 + 4 > memory.text().end)
+        .map(|n| LinkerError::SegmentOverflow("text", memory.text_size, n.span));
+
+    data.into_iter().chain(text).collect()
+}
+
+/// whether the expression refers to the address of a label
+fn uses_label(expr: &Expr, map: &HashMap<String, Symbol>) -> bool {
+    expr.identifiers()
+        .iter()
+        .any(|id| matches!(map.get(*id), Some(symbol) if symbol.kind == SymbolKind::Label))
+}
+
+/// add an identifier definition to the map, keeping the first one if it is already present
+///
+/// constants defined with .set replace earlier ones defined with .set,
+/// nodes without an identifier are skipped
+fn define(
+    map: &mut HashMap<String, Symbol>,
+    identifier: &str,
+    symbol: Symbol,
+) -> Result<(), LinkerError> {
+    if identifier.is_empty() {
+        return Ok(());
+    }
+
+    match map.entry(identifier.to_string()) {
+        Entry::Vacant(entry) => {
+            entry.insert(symbol);
+            Ok(())
+        }
+        Entry::Occupied(mut entry)
+            if entry.get().kind == SymbolKind::RedefinableConstant
+                && symbol.kind == SymbolKind::RedefinableConstant =>
+        {
+            entry.insert(symbol);
+            Ok(())
+        }
+        Entry::Occupied(entry) => Err(LinkerError::RedefinedIdentifier(
+            identifier.to_string(),
+            symbol.span,
+            entry.get().span,
+        )),
+    }
+}
+
+/// # Expansion
+///
+/// the instructions an la or ja was expanded to by the parser,
+/// recognised by the span they share
+struct Expansion {
+    start: usize,
+    len: usize,
+    jump: bool,
+    rd: u8,
+    target: Expr,
+}
+
+/// find every la and ja expansion among the instructions
+fn expansions(instructions: &[InstructionNode]) -> Vec<Expansion> {
+    use Instr::*;
+
+    let mut found = Vec::new();
+    let mut i = 0;
+
+    while i < instructions.len() {
+        let span = instructions[i].span;
+        let len = instructions[i..]
+            .iter()
+            .take_while(|n| n.span == span)
+            .count();
+        let ops: Vec<Instr> = instructions[i..i + len]
+            .iter()
+            .map(|n| n.op.clone())
+            .collect();
+
+        let jump = match ops[..] {
+            [Lui, Addi, Sl, Sr, Add] => Some(false),
+            [Lui, Addi, Sl, Sr, Add, J] => Some(true),
+            _ => None,
+        };
+
+        if let (Some(jump), [lui, addi, ..]) = (jump, &instructions[i..i + len]) {
+            // the parser loads the address with %hi and %lo, or with their values if it knew it
+            let target = match &lui.imm_expr {
+                Some(Expr::Unary(Op::Hi, target)) => (**target).clone(),
+                _ => Expr::Value((lui.imm << 16) | (addi.imm & 0xffff)),
             };
 
-            match instrnode.op {
-                Instr::Beq | Instr::Bne => {
-                    // beq, bne require an offset if they have an identifier
-                    instrnode.imm = target_address as i64 - instrnode.address as i64;
-                }
-                Instr::Addi => {
-                    // addi adds the bottom half of the address only
-                    instrnode.imm = (target_address & 0xffff) as i64;
-                }
-                Instr::Lui => {
-                    // lui adds the top half of the identifier only
-                    instrnode.imm = ((target_address & 0xffff0000) >> 16) as i64;
+            found.push(Expansion {
+                start: i,
+                len,
+                jump,
+                rd: addi.rd,
+                target,
+            });
+        }
+
+        i += len;
+    }
+
+    found
+}
+
+/// whether a word can be loaded with a single addi, which sign extends its immediate
+fn fits_addi(value: i64) -> bool {
+    let value = value as u32 as i32 as i64;
+    Op::Lo.unary(value) == value
+}
+
+/// whether a word can be loaded with a single addi or lui
+pub(crate) fn fits_single(value: i64) -> bool {
+    fits_addi(value) || Op::Lo.unary(value) == 0
+}
+
+/// number of instructions the expansion needs to reach value,
+/// the full expansion is kept if the value is not known
+fn shortest(expansion: &Expansion, value: Option<i64>) -> usize {
+    let load = match value {
+        Some(value) if WORD_RANGE.contains(&value) && fits_single(value) => 1,
+        Some(value) if WORD_RANGE.contains(&value) => 2,
+        _ => return expansion.len,
+    };
+
+    load + expansion.jump as usize
+}
+
+/// the instructions of an expansion shortened to size.
+/// Loads go through $at for ja, the jump stores the return address in rd
+fn shorten(
+    original: &[InstructionNode],
+    expansion: &Expansion,
+    size: usize,
+    value: Option<i64>,
+) -> Vec<InstructionNode> {
+    let first = &original[expansion.start];
+    let rd = if expansion.jump { 1 } else { expansion.rd };
+    let target = |op| Some(Expr::Unary(op, Box::new(expansion.target.clone())));
+
+    let node = |op, rd, ra, imm_expr| InstructionNode {
+        op,
+        rd,
+        ra,
+        rb: 0,
+        imm: 0,
+        identifier: String::new(),
+        label_span: Span::default(),
+        imm_expr,
+        address: 0,
+        placement: None,
+        span: first.span,
+    };
+
+    let mut nodes = match size - expansion.jump as usize {
+        1 if value.is_some_and(|v| !fits_addi(v)) => {
+            vec![node(Instr::Lui, rd, 0, target(Op::Hi))]
+        }
+        1 => vec![node(Instr::Addi, rd, 0, target(Op::Lo))],
+        _ => vec![
+            node(Instr::Lui, rd, 0, target(Op::Hia)),
+            node(Instr::Addi, rd, rd, target(Op::Lo)),
+        ],
+    };
+
+    if expansion.jump {
+        nodes.push(node(Instr::J, expansion.rd, 1, None));
+    }
+
+    nodes[0].identifier = first.identifier.clone();
+    nodes[0].label_span = first.label_span;
+    nodes[0].placement = first.placement;
+    nodes
+}
+
+/// lay out the instructions with every expansion shortened to its size,
+/// moving everything after a shortened expansion up
+///
+/// code placed with .org keeps its address and code placed with .align is aligned again
+fn layout(
+    original: &[InstructionNode],
+    expansions: &[Expansion],
+    sizes: &[usize],
+    values: &[Option<i64>],
+) -> Vec<InstructionNode> {
+    let mut instructions = Vec::with_capacity(original.len());
+    let mut address = original.first().map_or(0, |node| node.address as u64);
+    let mut next = expansions.iter().zip(sizes).zip(values).peekable();
+    let mut i = 0;
+
+    while i < original.len() {
+        match original[i].placement {
+            Some(Placement::Org(at)) => address = at as u64,
+            Some(Placement::Align(boundary)) => address = address.next_multiple_of(boundary as u64),
+            None => (),
+        }
+
+        match next.peek() {
+            Some(((expansion, size), value)) if expansion.start == i => {
+                let nodes = if **size == expansion.len {
+                    original[i..i + expansion.len].to_vec()
+                } else {
+                    shorten(original, expansion, **size, **value)
+                };
+
+                for mut node in nodes {
+                    node.address = address as u32;
+                    instructions.push(node);
+                    address += 4;
                 }
-                _ => (),
+
+                i += expansion.len;
+                next.next();
+            }
+            _ => {
+                instructions.push(InstructionNode {
+                    address: address as u32,
+                    ..original[i].clone()
+                });
+                address += 4;
+                i += 1;
             }
         }
     }
 
-    Ok(tree)
+    instructions
+}
+
+/// # Relax
+///
+/// shortens la and ja expansions whose address is known to fit fewer instructions:
+/// la becomes a single addi or lui, or a lui and addi pair,
+/// ja becomes the same load into $at followed by the jump
+///
+/// shortening moves the labels after it, which can change the sequence another address needs,
+/// so the text segment is laid out again until the addresses converge.
+/// Expansions start out as short as possible and only ever grow, so this always ends
+pub fn relax(mut tree: ProgramTree) -> ProgramTree {
+    let original = tree.instructions.clone();
+    let expansions = expansions(&original);
+
+    let mut sizes: Vec<usize> = expansions.iter().map(|e| shortest(e, Some(0))).collect();
+    let mut values = vec![None; expansions.len()];
+
+    loop {
+        tree.instructions = layout(&original, &expansions, &sizes, &values);
+
+        let (map, _) = symbols(&tree);
+        let lookup = |id: &str| map.get(id).map(|symbol| symbol.value);
+
+        values = expansions
+            .iter()
+            .map(|e| e.target.evaluate(&lookup).ok())
+            .collect();
+
+        let mut grown = false;
+        for ((expansion, size), value) in expansions.iter().zip(&mut sizes).zip(&values) {
+            let needed = shortest(expansion, *value);
+            if needed > *size {
+                *size = needed;
+                grown = true;
+            }
+        }
+
+        if !grown {
+            // the addresses did not move, pick the sequences for the final values
+            tree.instructions = layout(&original, &expansions, &sizes, &values);
+            return tree;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -96,14 +652,40 @@ mod tests {
     // tests if linking is performed correctly
     #[test]
     fn linktest() {
+        let number = |op, x| {
+            Expr::Binary(
+                op,
+                Box::new(Expr::Identifier("number".to_string())),
+                Box::new(Expr::Value(x)),
+            )
+        };
+
         let tree_in = ProgramTree {
-            data: vec![DataNode {
-                identifier: "number".to_string(),
-                address: 0x1000_0004,
-                block: Bl::Word,
-                data: 5,
-                num: 1,
-            }],
+            constants: vec![],
+            data: vec![
+                DataNode {
+                    identifier: "number".to_string(),
+                    label_span: Span::default(),
+                    address: 0x1000_0004,
+                    block: Bl::Word,
+                    data: 5,
+                    data_expr: None,
+                    payload: vec![],
+                    num: 1,
+                    span: Span::default(),
+                },
+                DataNode {
+                    identifier: "pointer".to_string(),
+                    label_span: Span::default(),
+                    address: 0x1000_0008,
+                    block: Bl::Word,
+                    data: 0,
+                    data_expr: Some(number(Op::Add, 8)),
+                    payload: vec![],
+                    num: 1,
+                    span: Span::default(),
+                },
+            ],
             instructions: vec![
                 InstructionNode {
                     op: Instr::Lui,
@@ -112,8 +694,11 @@ mod tests {
                     rb: 0,
                     imm: 0,
                     identifier: "beginning".to_string(),
-                    imm_identifier: "number".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(number(Op::Shr, 16)),
                     address: 0,
+                    placement: None,
+                    span: Span::default(),
                 },
                 InstructionNode {
                     op: Instr::Addi,
@@ -122,8 +707,11 @@ mod tests {
                     rb: 0,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "number".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(number(Op::And, 0xffff)),
                     address: 4,
+                    placement: None,
+                    span: Span::default(),
                 },
                 InstructionNode {
                     op: Instr::Beq,
@@ -132,20 +720,41 @@ mod tests {
                     rb: 0,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "beginning".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(Expr::Identifier("beginning".to_string())),
                     address: 8,
+                    placement: None,
+                    span: Span::default(),
                 },
             ],
         };
 
         let tree_out = ProgramTree {
-            data: vec![DataNode {
-                identifier: "number".to_string(),
-                address: 0x1000_0004,
-                block: Bl::Word,
-                data: 5,
-                num: 1,
-            }],
+            constants: vec![],
+            data: vec![
+                DataNode {
+                    identifier: "number".to_string(),
+                    label_span: Span::default(),
+                    address: 0x1000_0004,
+                    block: Bl::Word,
+                    data: 5,
+                    data_expr: None,
+                    payload: vec![],
+                    num: 1,
+                    span: Span::default(),
+                },
+                DataNode {
+                    identifier: "pointer".to_string(),
+                    label_span: Span::default(),
+                    address: 0x1000_0008,
+                    block: Bl::Word,
+                    data: 0x1000_000c,
+                    data_expr: Some(number(Op::Add, 8)),
+                    payload: vec![],
+                    num: 1,
+                    span: Span::default(),
+                },
+            ],
             instructions: vec![
                 InstructionNode {
                     op: Instr::Lui,
@@ -154,8 +763,11 @@ mod tests {
                     rb: 0,
                     imm: 0x1000,
                     identifier: "beginning".to_string(),
-                    imm_identifier: "number".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(number(Op::Shr, 16)),
                     address: 0,
+                    placement: None,
+                    span: Span::default(),
                 },
                 InstructionNode {
                     op: Instr::Addi,
@@ -164,8 +776,11 @@ mod tests {
                     rb: 0,
                     imm: 0x0004,
                     identifier: "".to_string(),
-                    imm_identifier: "number".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(number(Op::And, 0xffff)),
                     address: 4,
+                    placement: None,
+                    span: Span::default(),
                 },
                 InstructionNode {
                     op: Instr::Beq,
@@ -174,8 +789,11 @@ mod tests {
                     rb: 0,
                     imm: -8,
                     identifier: "".to_string(),
-                    imm_identifier: "beginning".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(Expr::Identifier("beginning".to_string())),
                     address: 8,
+                    placement: None,
+                    span: Span::default(),
                 },
             ],
         };
@@ -189,12 +807,17 @@ mod tests {
     #[test]
     fn linkerror() {
         let tree = ProgramTree {
+            constants: vec![],
             data: vec![DataNode {
                 identifier: "number1".to_string(),
+                label_span: Span::default(),
                 address: 0x1000_0004,
                 block: Bl::Word,
                 data: 5,
+                data_expr: None,
+                payload: vec![],
                 num: 1,
+                span: Span::default(),
             }],
             instructions: vec![InstructionNode {
                 op: Instr::Lui,
@@ -203,14 +826,284 @@ mod tests {
                 rb: 0,
                 imm: 0x1000,
                 identifier: "".to_string(),
-                imm_identifier: "number2".to_string(),
+                label_span: Span::default(),
+                imm_expr: Some(Expr::Identifier("number2".to_string())),
                 address: 0,
+                placement: None,
+                span: Span::default(),
+            }],
+        };
+
+        assert_eq!(
+            link(tree),
+            Err(vec![LinkerError::UnknownIdentifier(
+                "number2".to_string(),
+                Span::default()
+            )])
+        )
+    }
+
+    // tests if code and data placed over each other is reported once per pair
+    #[test]
+    fn overlapping_regions() {
+        let span = |row| Span {
+            file: 0,
+            row,
+            col: 4,
+            len: 8,
+        };
+        let word = |address, num, row| DataNode {
+            identifier: "".to_string(),
+            label_span: Span::default(),
+            address,
+            block: Bl::Space,
+            data: 0,
+            data_expr: None,
+            payload: vec![],
+            num,
+            span: span(row),
+        };
+        let instruction = |address, row| InstructionNode {
+            op: Instr::Addi,
+            rd: 1,
+            ra: 0,
+            rb: 0,
+            imm: 1,
+            identifier: "".to_string(),
+            label_span: Span::default(),
+            imm_expr: None,
+            address,
+            placement: None,
+            span: span(row),
+        };
+
+        let tree = ProgramTree {
+            constants: vec![],
+            data: vec![
+                word(0x1000_0000, 4, 1),
+                word(0x1000_0010, 1, 2),
+                word(0x1000_0008, 2, 3),
+                word(0x1000_0000, 0, 4),
+            ],
+            instructions: vec![
+                instruction(0, 6),
+                instruction(4, 7),
+                instruction(4, 8),
+                instruction(8, 9),
+            ],
+        };
+
+        assert_eq!(
+            link(tree),
+            Err(vec![
+                LinkerError::OverlappingRegions(span(3), span(1)),
+                LinkerError::OverlappingRegions(span(8), span(7)),
+            ])
+        );
+    }
+
+    // tests if every error is reported, not just the first one
+    #[test]
+    fn linkerrors() {
+        let span = |row| Span {
+            file: 0,
+            row,
+            col: 4,
+            len: 8,
+        };
+
+        let node = InstructionNode {
+            op: Instr::Beq,
+            rd: 0,
+            ra: 0,
+            rb: 0,
+            imm: 0,
+            identifier: "".to_string(),
+            label_span: Span::default(),
+            imm_expr: None,
+            address: 0,
+            placement: None,
+            span: Span::default(),
+        };
+
+        let tree = ProgramTree {
+            constants: vec![],
+            data: vec![DataNode {
+                identifier: "number".to_string(),
+                label_span: span(1),
+                address: 0x1000_0000,
+                block: Bl::Word,
+                data: 5,
+                data_expr: None,
+                payload: vec![],
+                num: 1,
+                span: span(1),
             }],
+            instructions: vec![
+                InstructionNode {
+                    identifier: "number".to_string(),
+                    label_span: span(3),
+                    imm_expr: Some(Expr::Identifier("loop".to_string())),
+                    span: span(3),
+                    ..node.clone()
+                },
+                InstructionNode {
+                    imm_expr: Some(Expr::Identifier("end".to_string())),
+                    address: 4,
+                    span: span(4),
+                    ..node
+                },
+            ],
         };
 
         assert_eq!(
             link(tree),
-            Err(LinkerError::UnknownIdentifier("number2".to_string()))
+            Err(vec![
+                LinkerError::RedefinedIdentifier("number".to_string(), span(3), span(1)),
+                LinkerError::UnknownIdentifier("loop".to_string(), span(3)),
+                LinkerError::UnknownIdentifier("end".to_string(), span(4)),
+            ])
         )
     }
+
+    // tests if branches too far from their target are reported
+    #[test]
+    fn branch_range() {
+        let node = InstructionNode {
+            op: Instr::Bne,
+            rd: 0,
+            ra: 1,
+            rb: 0,
+            imm: 0,
+            identifier: "".to_string(),
+            label_span: Span::default(),
+            imm_expr: None,
+            address: 0,
+            placement: None,
+            span: Span::default(),
+        };
+
+        let tree = |target: u32| ProgramTree {
+            constants: vec![],
+            data: vec![],
+            instructions: vec![
+                InstructionNode {
+                    identifier: "start".to_string(),
+                    imm_expr: Some(Expr::Identifier("end".to_string())),
+                    ..node.clone()
+                },
+                InstructionNode {
+                    identifier: "end".to_string(),
+                    imm_expr: Some(Expr::Identifier("start".to_string())),
+                    address: target,
+                    ..node.clone()
+                },
+            ],
+        };
+
+        assert!(link(tree(0x7fff)).is_ok());
+
+        assert_eq!(
+            link(tree(0x8000)),
+            Err(vec![LinkerError::ImmediateOutOfRange(
+                0x8000,
+                -0x8000..=0x7fff,
+                Span::default()
+            )])
+        );
+
+        assert_eq!(
+            link(tree(0x8001)),
+            Err(vec![
+                LinkerError::ImmediateOutOfRange(0x8001, -0x8000..=0x7fff, Span::default()),
+                LinkerError::ImmediateOutOfRange(-0x8001, -0x8000..=0x7fff, Span::default())
+            ])
+        );
+    }
+
+    // tests if la and ja are shortened until the addresses converge
+    #[test]
+    fn relaxation() {
+        let relaxed = |padding: usize| {
+            let listing = format!(
+                ".text\nmain: la $t0, end\n{}end: ja $ra, main\n",
+                "add $t0, $t0, $t0\n".repeat(padding)
+            );
+            let (tree, errors) = crate::parser::parse_with_recovery(
+                &[crate::SourceFile::new("", &listing)],
+                &MemoryMap::default(),
+            );
+            assert_eq!(errors, vec![]);
+
+            link(relax(tree))
+                .unwrap()
+                .instructions
+                .into_iter()
+                .filter(|n| n.op != Instr::Add)
+                .map(|n| (n.op, n.rd, n.ra, n.imm, n.address))
+                .collect::<Vec<_>>()
+        };
+
+        // end is only reachable with addi once la is shortened
+        assert_eq!(
+            relaxed(0x1ffe),
+            vec![
+                (Instr::Addi, 9, 0, 0x7ffc, 0),
+                (Instr::Addi, 1, 0, 0, 0x7ffc),
+                (Instr::J, 14, 1, 0, 0x8000),
+            ]
+        );
+
+        // a single instruction would move end to 0x8000, which addi can not reach
+        assert_eq!(
+            relaxed(0x1fff),
+            vec![
+                (Instr::Lui, 9, 0, 1, 0),
+                (Instr::Addi, 9, 9, -0x7ffc, 4),
+                (Instr::Addi, 1, 0, 0, 0x8004),
+                (Instr::J, 14, 1, 0, 0x8008),
+            ]
+        );
+    }
+
+    // tests if every identifier ends up in the table with the bytes up to the next label
+    #[test]
+    fn symbol_table_test() {
+        let listing = "\
+.set STEP, 1
+.set STEP, 2
+.data
+    table: .word 1, 2, 3
+    .space 2
+    port: .addr 0xffff0000
+    message: .ascii \"hi\"
+.text
+main:
+    la $t0, table
+    j $zero, $ra
+end:
+    add $t0, $t0, $t0
+";
+        let tree = crate::parser::parse(listing).unwrap();
+        let tree = link(relax(tree)).unwrap();
+
+        let symbol = |name: &str, value, segment, size| TableSymbol {
+            name: name.to_string(),
+            value,
+            segment,
+            size,
+            file: 0,
+        };
+        assert_eq!(
+            symbol_table(&tree),
+            vec![
+                symbol("main", 0, Segment::Text, 8),
+                symbol("STEP", 2, Segment::Absolute, 0),
+                symbol("end", 8, Segment::Text, 4),
+                symbol("table", 0x1000_0000, Segment::Data, 20),
+                symbol("message", 0x1000_0014, Segment::Data, 4),
+                symbol("port", 0xffff_0000, Segment::Absolute, 4),
+            ]
+        );
+    }
 }
@@ -0,0 +1,187 @@
+use crate::disassembler::print_instruction;
+use crate::printer::{data_words, encode, Endianness};
+use crate::types::*;
+use crate::{ProgramTree, SourceFile};
+use std::collections::BTreeMap;
+
+/// number of data words shown on one line of the listing
+const WORDS_PER_LINE: usize = 4;
+
+/// width of the column holding the encoded words
+const WORDS_WIDTH: usize = 9 * WORDS_PER_LINE - 1;
+
+/// what a source line assembled to
+enum Entry<'a> {
+    /// instructions sharing a statement, more than one for an expanded pseudoinstruction
+    Instructions(Vec<&'a InstructionNode>),
+    /// a data node starting at its address
+    Data(u32, Vec<u32>),
+}
+
+/// # Listing
+///
+/// print every line of the source files next to the address and the words it assembled to
+///
+/// expansions of pseudoinstructions are indented beneath the line they come from.
+/// Data directives show the words they fill memory with, four to a line,
+/// runs of a single repeated word are shown once with their length
+pub fn listing(sources: &[SourceFile], tree: &ProgramTree, endianness: Endianness) -> String {
+    // the entries of every line, in the order they appear in memory
+    let mut entries: BTreeMap<(usize, u32), Vec<Entry>> = BTreeMap::new();
+
+    for node in &tree.data {
+        if node.block == Bl::Addr {
+            continue;
+        }
+        entries
+            .entry((node.span.file, node.span.row))
+            .or_default()
+            .push(Entry::Data(node.address, data_words(node, endianness)));
+    }
+
+    for node in &tree.instructions {
+        let line = entries.entry((node.span.file, node.span.row)).or_default();
+        match line.last_mut() {
+            Some(Entry::Instructions(nodes)) if nodes[0].span == node.span => nodes.push(node),
+            _ => line.push(Entry::Instructions(vec![node])),
+        }
+    }
+
+    let mut listing = String::new();
+
+    for (file, source) in sources.iter().enumerate() {
+        listing.push_str(&format!("{}:\n", source.name));
+
+        for (row, text) in source.contents.lines().enumerate() {
+            let Some(line) = entries.get(&(file, row as u32)) else {
+                listing.push_str(&print_line(None, "", row + 1, text));
+                continue;
+            };
+
+            // the source text goes on the first line printed for it
+            let mut text = Some(text);
+            let indent: String = text
+                .unwrap_or_default()
+                .chars()
+                .take_while(|c| c.is_whitespace())
+                .collect();
+
+            for entry in line {
+                match entry {
+                    Entry::Instructions(nodes) if nodes.len() == 1 => {
+                        let word = format!("{:08X}", encode(nodes[0]));
+                        listing.push_str(&print_line(
+                            Some(nodes[0].address),
+                            &word,
+                            row + 1,
+                            text.take().unwrap_or_default(),
+                        ));
+                    }
+                    Entry::Instructions(nodes) => {
+                        if let Some(text) = text.take() {
+                            listing.push_str(&print_line(
+                                Some(nodes[0].address),
+                                "",
+                                row + 1,
+                                text,
+                            ));
+                        }
+                        for node in nodes {
+                            let word = format!("{:08X}", encode(node));
+                            let expansion = format!("{indent}    {}", print_instruction(node));
+                            listing.push_str(&print_line(Some(node.address), &word, 0, &expansion));
+                        }
+                    }
+                    Entry::Data(address, words) => {
+                        for (i, chunk) in print_words(words).into_iter().enumerate() {
+                            let address = address + (4 * WORDS_PER_LINE * i) as u32;
+                            let number = if text.is_some() { row + 1 } else { 0 };
+                            listing.push_str(&print_line(
+                                Some(address),
+                                &chunk,
+                                number,
+                                text.take().unwrap_or_default(),
+                            ));
+                        }
+                    }
+                }
+            }
+
+            // a line whose nodes took up no memory is still listed
+            if let Some(text) = text {
+                listing.push_str(&print_line(None, "", row + 1, text));
+            }
+        }
+    }
+
+    listing
+}
+
+/// print one line of the listing, leaving out the address if there is none
+/// and the line number if it is 0
+fn print_line(address: Option<u32>, words: &str, number: usize, text: &str) -> String {
+    let address = match address {
+        Some(address) => format!("{address:08X}"),
+        None => " ".repeat(8),
+    };
+    let number = match number {
+        0 => String::new(),
+        n => n.to_string(),
+    };
+
+    format!("{address}  {words:<WORDS_WIDTH$}  {number:>5}  {text}")
+        .trim_end()
+        .to_string()
+        + "\n"
+}
+
+/// split the words of a data node into the chunks shown on each line
+fn print_words(words: &[u32]) -> Vec<String> {
+    match words {
+        [] => vec![],
+        [first, ..] if words.len() > WORDS_PER_LINE && words.iter().all(|w| w == first) => {
+            vec![format!("{first:08X} x {}", words.len())]
+        }
+        _ => words
+            .chunks(WORDS_PER_LINE)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .map(|w| format!("{w:08X}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{link_sources, Options};
+
+    #[test]
+    fn listing_test() {
+        let sources = [SourceFile::new(
+            "main.s",
+            ".data\n    table: .word 7 : 6, 1\n.text\nmain:\n    push $t0\n    lui $t0, 0x1000\n",
+        )];
+        let tree = link_sources(&sources, &Options::default()).unwrap();
+
+        assert_eq!(
+            listing(&sources, &tree, Endianness::Little),
+            "\
+main.s:
+                                                   1  .data
+10000000  00000007 x 6                             2      table: .word 7 : 6, 1
+10000018  00000001
+                                                   3  .text
+                                                   4  main:
+00000000                                           5      push $t0
+00000000  CFF0FFFC                                            addi $sp, $sp, -4
+00000004  F09F0000                                            sw $t0, $sp
+00000008  D9001000                                 6      lui $t0, 0x1000
+"
+        );
+    }
+}
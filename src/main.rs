@@ -1,84 +1,385 @@
 use greg::*;
 use std::env;
-use std::fs;
+use std::ops::Range;
 use std::process::exit;
 
+/// # Exit codes
+///
+/// each kind of failure exits with its own code, so build scripts can react to it
+const EXIT_USAGE: i32 = 1;
+const EXIT_IO: i32 = 2;
+const EXIT_PARSE: i32 = 3;
+const EXIT_LINK: i32 = 4;
+const EXIT_RUN: i32 = 5;
+
+/// number of instructions greg run executes before giving up on the program
+const RUN_STEP_LIMIT: u64 = 10_000_000;
+
+/// # Command line errors
+///
+/// everything that can make a run of the assembler fail
+enum CliError {
+    Usage,
+    Conflict(&'static str, &'static str),
+    Io(String, std::io::Error),
+    Assembler(Vec<AssemblerError>),
+    Disassembler(String, DisassemblerError),
+    Simulator(SimulatorError),
+    MemoryMap(String, MemoryMapError),
+    Printer(printer::PrinterError),
+}
+
+/// # Output formats
+///
+/// what the assembled program is written out as
+enum Format {
+    /// intel HEX, both memories in one file
+    Hex(printer::HexAddressing),
+    /// motorola S-records, both memories in one file
+    Srec,
+    /// raw bytes, one file for each memory
+    Bin,
+    /// raw bytes of the whole physical memory in one file
+    Image,
+    /// verilog $readmemh or $readmemb files, one for each memory
+    Readmem(printer::Radix),
+    /// xilinx COE files, one for each memory
+    Coe,
+    /// intel MIF files, one for each memory
+    Mif,
+    /// an ELF32 executable with the symbol table
+    Elf,
+}
+
+impl Format {
+    /// name of the output file if none is given
+    fn default_output(&self) -> &'static str {
+        match self {
+            Self::Hex(_) => "a.hex",
+            Self::Srec => "a.srec",
+            Self::Elf => "a.elf",
+            Self::Bin | Self::Readmem(_) | Self::Coe | Self::Mif => "a",
+            Self::Image => "a.bin",
+        }
+    }
+}
+
+/// # Startup code
+///
+/// selects the code placed before the input files
+enum Crt0 {
+    /// the built-in startup code, calling the entry symbol
+    Default,
+    /// startup code read from a file
+    File(String),
+    /// nothing, for bare-metal images that start at their first instruction
+    None,
+}
+
+/// # Command line arguments
+///
+/// everything parse_args gathers from the command line
+struct Args {
+    input_files: Vec<String>,
+    output_file: Option<String>,
+    listing_file: Option<String>,
+    map_file: Option<String>,
+    json_map_file: Option<String>,
+    format: Format,
+    debug_lines: bool,
+    options: Options,
+    crt0: Crt0,
+    entry: String,
+}
+
 /// # Greg the assembler
 fn main() {
     // argument handling
     let args: Vec<String> = env::args().collect(); //take in two filenames (input, output)
-    let (input_files, output_file, offset) = parse_args(args);
-
-    // insert this before every program
-    let prelude = "\
-    .text
-    and $zero, $zero, $zero
-    and $zero, $zero, $zero
-    and $zero, $zero, $zero
-    and $zero, $zero, $zero
-    and $zero, $zero, $zero
-    lui $gv, 0x1000
-    lui $sp, 0x8000
-    ja $ra, main
-    ";
-
-    let mut program_listing = String::from(prelude);
-
-    let mut input_file_sizes: Vec<(String, usize)> = Vec::new();
-
-    for file in input_files {
-        let file_contents = match fs::read_to_string(&file) {
-            Err(e) => panic!("Error reading input: {e}"),
-            Ok(str) => str,
-        };
 
-        // save the input file name and length
-        input_file_sizes.push((file, file_contents.lines().count()));
-
-        program_listing.push_str(&file_contents);
-        program_listing.push('\n');
-    }
-
-    // assemble file, panicking on errors
-    let hex = match assemble(&program_listing, offset) {
-        Ok(hex) => hex,
-        Err(e) => match &e {
-            AssemblerError::ParserError(ParserError::Incomplete(loc))
-            | AssemblerError::ParserError(ParserError::InvalidToken(loc))
-            | AssemblerError::ParserError(ParserError::NegativeSpace(loc))
-            | AssemblerError::ParserError(ParserError::CodeOutsideSegment(loc)) => {
-                let mut file = String::new();
-                let mut row = loc.row - prelude.lines().count() as u32;
-                for (file0, lines) in input_file_sizes {
-                    file = file0;
-                    if row > (lines as u32) {
-                        row -= lines as u32;
-                    } else {
-                        break;
-                    }
+    let mut sources = Vec::new();
+
+    if let Err(e) = run(args, &mut sources) {
+        let code = match e {
+            CliError::Usage => {
+                usage_hint();
+                EXIT_USAGE
+            }
+            CliError::Conflict(first, second) => {
+                eprintln!("Error: {first} cannot be combined with {second}");
+                EXIT_USAGE
+            }
+            CliError::Io(file, e) => {
+                eprintln!("Error: {file}: {e}");
+                EXIT_IO
+            }
+            CliError::Disassembler(file, e) => {
+                eprintln!("Error: {file}: {e}");
+                EXIT_PARSE
+            }
+            CliError::MemoryMap(file, e) => {
+                eprintln!("Error: {file}: {e}");
+                EXIT_USAGE
+            }
+            CliError::Printer(e) => {
+                eprintln!("Error: {e}");
+                EXIT_LINK
+            }
+            CliError::Simulator(e) => {
+                eprintln!("Error: {e}");
+                EXIT_RUN
+            }
+            CliError::Assembler(errors) => {
+                for e in &errors {
+                    eprintln!("{}\n", e.render(&sources));
+                }
+                eprintln!("{} error(s) found, no output written", errors.len());
+
+                // parser errors take precedence, linker errors may follow from them
+                if errors
+                    .iter()
+                    .any(|e| matches!(e, AssemblerError::ParserError(_)))
+                {
+                    EXIT_PARSE
+                } else {
+                    EXIT_LINK
                 }
-                panic!(
-                    "{}: file: {} line: {} column: {}",
-                    e,
-                    file,
-                    row + 2, // one line for counting from 1, one gets eaten by concatenation
-                    loc.col
-                );
             }
+        };
+        exit(code);
+    }
+}
 
-            _ => panic!("{}", e),
-        },
-    };
+/// read the input files, assemble them and write out the result
+///
+/// the source files read are kept in sources, so errors can be rendered against them
+fn run(args: Vec<String>, sources: &mut Vec<SourceFile>) -> Result<(), CliError> {
+    match args.get(1).map(String::as_str) {
+        Some("dis") => return run_disassembler(args),
+        Some("run") => return run_simulator(args, sources),
+        _ => (),
+    }
+
+    let args = parse_args(args)?;
+    let output_file =
+        (args.output_file.clone()).unwrap_or(args.format.default_output().to_string());
+
+    read_sources(&args, sources)?;
+
+    // assemble file, reporting all errors found
+    let tree = link_sources(sources, &args.options)
+        .map_err(|errors| CliError::Assembler(crt0::entry_errors(errors, sources)))?;
+
+    if let Some(file) = args.listing_file {
+        let listing = listing::listing(sources, &tree, args.options.endianness);
+        io::print_to_file(&file, listing).map_err(|e| CliError::Io(file, e))?;
+    }
+
+    // symbol tables for tools that label addresses
+    if args.map_file.is_some() || args.json_map_file.is_some() {
+        let table = linker::symbol_table(&tree);
+        if let Some(file) = args.map_file {
+            let map = symbols::print_map(&table, sources);
+            io::print_to_file(&file, map).map_err(|e| CliError::Io(file, e))?;
+        }
+        if let Some(file) = args.json_map_file {
+            let json = symbols::print_json(&table, sources);
+            io::print_to_file(&file, json).map_err(|e| CliError::Io(file, e))?;
+        }
+    }
 
     // print out assembled binary
-    io::print_to_file(&output_file, hex);
+    let lines = args.debug_lines.then_some(&sources[..]);
+    write_output(&args.format, tree, lines, &args.options, &output_file)
+}
+
+/// write the program out in the selected format,
+/// formats with one file for each memory add the memory to the name of the output file
+///
+/// if the sources are given, formats that carry debug information map the program back to them
+fn write_output(
+    format: &Format,
+    tree: ProgramTree,
+    sources: Option<&[SourceFile]>,
+    options: &Options,
+    output_file: &str,
+) -> Result<(), CliError> {
+    let write = |file: String, buffer: Vec<u8>| {
+        io::print_to_file(&file, buffer).map_err(|e| CliError::Io(file, e))
+    };
+
+    let binary = printer::print_binary_with(tree.clone(), options.endianness, &options.memory);
+    let offset = options.memory.physical_data_offset;
+    let memory = &options.memory;
+
+    // formats with one file for each memory, printed by print
+    let (text_runs, data_runs) = printer::placed_runs(&tree, memory);
+    let each_memory = |extension: &str, print: &PrintMemory<'_>| {
+        let memories = [
+            ("text", &binary.instructions, &text_runs, memory.text_depth),
+            ("data", &binary.data, &data_runs, memory.data_depth),
+        ];
+        for (name, words, runs, depth) in memories {
+            let file = std::path::Path::new(output_file)
+                .with_extension(format!("{name}.{extension}"))
+                .to_string_lossy()
+                .into_owned();
+            write(file, print(words, runs, depth).map_err(CliError::Printer)?)?;
+        }
+        Ok(())
+    };
+
+    match format {
+        Format::Hex(addressing) => {
+            let hex =
+                printer::print_hex_with(binary, offset, *addressing).map_err(CliError::Printer)?;
+            write(output_file.to_owned(), hex.into_bytes())
+        }
+        Format::Srec => write(
+            output_file.to_owned(),
+            printer::print_srec(binary, offset).into_bytes(),
+        ),
+        Format::Image => {
+            let image = printer::print_image(&binary, memory, options.endianness)
+                .map_err(CliError::Printer)?;
+            write(output_file.to_owned(), image)
+        }
+        Format::Bin => each_memory("bin", &|words, _, _| {
+            Ok(printer::print_raw(words, options.endianness))
+        }),
+        Format::Readmem(radix) => {
+            let extension = match radix {
+                printer::Radix::Hex => "memh",
+                printer::Radix::Bin => "memb",
+            };
+            each_memory(extension, &|words, runs, _| {
+                Ok(printer::print_readmem_with(words, runs, *radix).into_bytes())
+            })
+        }
+        Format::Coe => each_memory("coe", &|words, _, depth| {
+            printer::print_coe(words, depth).map(String::into_bytes)
+        }),
+        Format::Mif => each_memory("mif", &|words, _, depth| {
+            printer::print_mif(words, depth).map(String::into_bytes)
+        }),
+        Format::Elf => {
+            let table = linker::symbol_table(&tree);
+            let elf = printer::print_elf(&tree, &table, sources, options.endianness, memory);
+            write(output_file.to_owned(), elf)
+        }
+    }
+}
+
+/// the contents of an output file, or why it could not be printed
+type Printed = Result<Vec<u8>, printer::PrinterError>;
+
+/// prints one memory from its words, the runs of them that were placed and its depth
+type PrintMemory<'a> = dyn Fn(&[u32], &[Range<usize>], Option<u32>) -> Printed + 'a;
+
+/// read the input files, preceded by the startup code selected in args
+fn read_sources(args: &Args, sources: &mut Vec<SourceFile>) -> Result<(), CliError> {
+    match &args.crt0 {
+        Crt0::Default => sources.push(crt0::default(&args.entry, &args.options.memory)),
+        Crt0::File(file) => sources.push(read_source(file)?),
+        Crt0::None => (),
+    }
+
+    for file in &args.input_files {
+        sources.push(read_source(file)?);
+    }
+
+    Ok(())
+}
+
+/// read a single source file, naming it in the error if that fails
+fn read_source(file: &str) -> Result<SourceFile, CliError> {
+    io::read_source_file(file).map_err(|e| CliError::Io(file.to_owned(), e))
+}
+
+/// assemble the input files and execute them in the simulator, printing the final state
+///
+/// the state is printed even if the program fails, to help finding out why
+fn run_simulator(args: Vec<String>, sources: &mut Vec<SourceFile>) -> Result<(), CliError> {
+    // skip the mode, the rest of the arguments takes the same form
+    let args = parse_args(args[1..].to_vec())?;
+
+    read_sources(&args, sources)?;
+
+    let tree = link_sources(sources, &args.options).map_err(CliError::Assembler)?;
+
+    let mut simulator = sim::Simulator::load(&tree, &args.options);
+    let result = simulator.run(RUN_STEP_LIMIT);
+
+    for (address, value) in &simulator.output {
+        println!("0x{address:08X} <- 0x{value:08X}");
+    }
+
+    println!("{} steps, pc = 0x{:08X}", simulator.steps, simulator.pc);
+    for (i, value) in simulator.registers.iter().enumerate() {
+        let separator = if i % 4 == 3 { "\n" } else { "  " };
+        print!(
+            "{:>5} = 0x{value:08X}{separator}",
+            format!("${}", REGISTER_NAMES[i])
+        );
+    }
+
+    result.map_err(CliError::Simulator)
+}
+
+/// read a HEX file back and print the program listing it holds
+///
+/// the listing goes to the output file if one is given, otherwise to stdout
+fn run_disassembler(args: Vec<String>) -> Result<(), CliError> {
+    // skip the mode, the rest of the arguments takes the same form
+    let Args {
+        input_files,
+        output_file,
+        options,
+        ..
+    } = parse_args(args[1..].to_vec())?;
+
+    let input_file = match &input_files[..] {
+        [file] => file,
+        _ => return Err(CliError::Usage),
+    };
+
+    let hex = match io::read_source_file(input_file) {
+        Ok(source) => source.contents,
+        Err(e) => return Err(CliError::Io(input_file.to_owned(), e)),
+    };
+
+    // S-records start with an S, intel HEX records with a colon
+    let binary = match hex.trim_start().starts_with('S') {
+        true => disassembler::read_srec_with(&hex, &options.memory),
+        false => disassembler::read_hex_with(&hex, &options.memory),
+    }
+    .map_err(|e| CliError::Disassembler(input_file.to_owned(), e))?;
+    let listing = disassembler::disassemble_with(&binary, &options.memory);
+
+    match output_file {
+        Some(file) => io::print_to_file(&file, listing).map_err(|e| CliError::Io(file, e)),
+        None => {
+            print!("{listing}");
+            Ok(())
+        }
+    }
 }
 
-/// parse arguments given to the fucntion, exit with usage hint if something is not right
-fn parse_args(args: Vec<String>) -> (Vec<String>, String, u32) {
+/// parse arguments given to the fucntion, return a usage error if something is not right
+fn parse_args(args: Vec<String>) -> Result<Args, CliError> {
     let mut infiles: Vec<String> = Vec::new();
-    let mut outfile = "a.hex".to_string(); //output file defaults to "a"
-    let mut offset = 0x400;
+    let mut outfile = None;
+    let mut listfile = None;
+    let mut mapfile = None;
+    let mut jsonmapfile = None;
+    let mut format = Format::Hex(printer::HexAddressing::Linear);
+    let mut hex_addressing = printer::HexAddressing::Linear;
+    let mut debug_lines = false;
+    let mut offset = None;
+    let mut memory_file = None;
+    let mut options = Options::default();
+    let mut crt0 = Crt0::Default;
+    let mut entry = None;
 
     let mut args = args.iter();
 
@@ -87,7 +388,7 @@ fn parse_args(args: Vec<String>) -> (Vec<String>, String, u32) {
     // mandatory: acqure one argument for input file
     let arg = match args.next() {
         Some(str) => str,
-        None => usage_hint(),
+        None => return Err(CliError::Usage),
     };
     infiles.push(arg.to_owned());
 
@@ -98,18 +399,87 @@ fn parse_args(args: Vec<String>) -> (Vec<String>, String, u32) {
             // if -o option is invoked, capture outfile name and break out of the loop
             "-o" => {
                 outfile = match args.next() {
-                    Some(str) => str.to_owned(),
-                    None => usage_hint(),
+                    Some(str) => Some(str.to_owned()),
+                    None => return Err(CliError::Usage),
+                };
+            }
+            "-l" => {
+                listfile = match args.next() {
+                    Some(str) => Some(str.to_owned()),
+                    None => return Err(CliError::Usage),
+                };
+            }
+            "--map" => {
+                mapfile = match args.next() {
+                    Some(str) => Some(str.to_owned()),
+                    None => return Err(CliError::Usage),
+                };
+            }
+            "--map-json" => {
+                jsonmapfile = match args.next() {
+                    Some(str) => Some(str.to_owned()),
+                    None => return Err(CliError::Usage),
                 };
             }
             "-p" => {
                 let temp = match args.next() {
                     Some(str) => str.to_owned(),
-                    None => usage_hint(),
+                    None => return Err(CliError::Usage),
                 };
                 offset = match temp.parse::<u32>() {
-                    Ok(str) => str.to_owned(),
-                    Err(_) => usage_hint(),
+                    Ok(str) => Some(str.to_owned()),
+                    Err(_) => return Err(CliError::Usage),
+                };
+            }
+            "-m" => {
+                memory_file = match args.next() {
+                    Some(str) => Some(str.to_owned()),
+                    None => return Err(CliError::Usage),
+                };
+            }
+            "--format" => {
+                format = match args.next().map(String::as_str) {
+                    Some("hex") => Format::Hex(printer::HexAddressing::Linear),
+                    Some("srec") => Format::Srec,
+                    Some("bin") => Format::Bin,
+                    Some("image") => Format::Image,
+                    Some("readmemh") => Format::Readmem(printer::Radix::Hex),
+                    Some("readmemb") => Format::Readmem(printer::Radix::Bin),
+                    Some("coe") => Format::Coe,
+                    Some("mif") => Format::Mif,
+                    Some("elf") => Format::Elf,
+                    _ => return Err(CliError::Usage),
+                };
+            }
+            "--hex-addressing" => {
+                hex_addressing = match args.next().map(String::as_str) {
+                    Some("linear") => printer::HexAddressing::Linear,
+                    Some("segment") => printer::HexAddressing::Segment,
+                    _ => return Err(CliError::Usage),
+                };
+            }
+            "--endian" => {
+                options.endianness = match args.next().map(String::as_str) {
+                    Some("little") => Endianness::Little,
+                    Some("big") => Endianness::Big,
+                    _ => return Err(CliError::Usage),
+                };
+            }
+            // keep la and ja at their full length
+            "--no-relax" => options.relax = false,
+            // replace or leave out the startup code
+            "--crt0" => {
+                crt0 = match args.next() {
+                    Some(str) => Crt0::File(str.to_owned()),
+                    None => return Err(CliError::Usage),
+                };
+            }
+            "--no-crt0" => crt0 = Crt0::None,
+            "-g" => debug_lines = true,
+            "--entry" => {
+                entry = match args.next() {
+                    Some(str) => Some(str.to_owned()),
+                    None => return Err(CliError::Usage),
                 };
             }
             // otherwise keep rading input files
@@ -117,21 +487,90 @@ fn parse_args(args: Vec<String>) -> (Vec<String>, String, u32) {
         }
     }
 
-    (infiles, outfile, offset)
+    // only the built-in startup code calls the entry symbol
+    match (&entry, &crt0) {
+        (Some(_), Crt0::File(_)) => return Err(CliError::Conflict("--entry", "--crt0")),
+        (Some(_), Crt0::None) => return Err(CliError::Conflict("--entry", "--no-crt0")),
+        _ => (),
+    }
+    let entry = entry.unwrap_or(crt0::DEFAULT_ENTRY.to_string());
+
+    // the addressing only applies to HEX output, it may come before or after --format
+    if let Format::Hex(addressing) = &mut format {
+        *addressing = hex_addressing;
+    }
+
+    // the memory map is read first, so -p overrides its data offset
+    if let Some(file) = memory_file {
+        options.memory = read_memory_map(&file)?;
+    }
+    if let Some(offset) = offset {
+        options.memory.physical_data_offset = offset;
+    }
+
+    Ok(Args {
+        input_files: infiles,
+        output_file: outfile,
+        listing_file: listfile,
+        map_file: mapfile,
+        json_map_file: jsonmapfile,
+        format,
+        debug_lines,
+        options,
+        crt0,
+        entry,
+    })
+}
+
+/// read a memory map from a TOML file
+fn read_memory_map(file: &str) -> Result<MemoryMap, CliError> {
+    let config = read_source(file)?;
+    MemoryMap::from_toml(&config.contents).map_err(|e| CliError::MemoryMap(file.to_owned(), e))
 }
 
 /// # Usage hint
 ///
-/// display usage hint and exit if wrong number of arguments was read
-fn usage_hint() -> ! {
-    println!("------------------------- greg the assembler - v3.0.4 -------------------------");
-    println!("| Usage:                                                                      |");
-    println!("| greg [infile1] [infile2] ... -o [outfile] -p [physical memory .data offset] |");
-    println!("| infile1: First input file. Mandatory.                                       |");
-    println!("| infile*: Additional input files. Linked with main file during assembly.     |");
-    println!("| Arguments:                                                                  |");
-    println!("| -o | output file name - defaults to \"a.hex\"                                 |");
-    println!("| -p | offset of .data segment in physical memory - defaults to 0x400         |");
-    println!("-------------------------------------------------------------------------------");
-    exit(1);
+/// display usage hint if wrong arguments were given
+fn usage_hint() {
+    eprintln!("------------------------- greg the assembler - v3.0.4 -------------------------");
+    eprintln!("| Usage:                                                                      |");
+    eprintln!("| greg [infile1] [infile2] ... -o [outfile] -p [physical memory .data offset] |");
+    eprintln!("| infile1: First input file. Mandatory.                                       |");
+    eprintln!("| infile*: Additional input files. Linked with main file during assembly.     |");
+    eprintln!("| greg dis [infile] -o [outfile] -p [physical memory .data offset]            |");
+    eprintln!("| Disassembles an intel HEX or S-record file. Prints the listing if no        |");
+    eprintln!("| outfile is given.                                                           |");
+    eprintln!("| greg run [infile1] [infile2] ...                                            |");
+    eprintln!("| Runs the program in a simulator, prints I/O writes and the final registers. |");
+    eprintln!("| Arguments:                                                                  |");
+    eprintln!("| -o | output file name - defaults to \"a.hex\"                                 |");
+    eprintln!("| -l | listing file, showing each line next to its address and encoding       |");
+    eprintln!("| --map | map file listing every symbol, sorted by address                    |");
+    eprintln!("| --map-json | the same symbols as a JSON array, for scripts                  |");
+    eprintln!("| -p | offset of .data segment in physical memory - defaults to 0x400         |");
+    eprintln!("| -m | TOML file with the memory map: text_base, text_size, data_base,        |");
+    eprintln!("|    | data_size, stack_top, physical_data_offset, text_depth and data_depth  |");
+    eprintln!("| --endian little/big | order of bytes packed into words - defaults to little |");
+    eprintln!("| --format [format] | output format - defaults to hex                         |");
+    eprintln!("|   hex: intel HEX with both memories                                         |");
+    eprintln!("|   srec: motorola S-records with both memories, S19/S28/S37 as needed        |");
+    eprintln!("|   bin: raw [outfile].text.bin and [outfile].data.bin                        |");
+    eprintln!("|   image: one raw file with .data placed at its physical offset              |");
+    eprintln!("|          (-p), which may not lie beyond text_size of the memory map         |");
+    eprintln!("|   readmemh/readmemb: verilog [outfile].text.memh and .data.memh (or .memb)  |");
+    eprintln!("|   coe/mif: block RAM files [outfile].text.coe and .data.coe (or .mif),      |");
+    eprintln!("|            padded to text_depth and data_depth of the memory map            |");
+    eprintln!("|   elf: ELF32 executable with .text, .data, .bss and the symbol table        |");
+    eprintln!("| --hex-addressing linear/segment | extended address records of type 04 or    |");
+    eprintln!("|     02 in HEX output - defaults to linear, segment reaches below 0x100000   |");
+    eprintln!("| -g | add DWARF line numbers to elf output                                   |");
+    eprintln!("| --no-relax | always expand la and ja to their full length, so code timing   |");
+    eprintln!("|            | does not depend on the addresses they load                     |");
+    eprintln!("| --crt0 | file with the startup code run before the program                  |");
+    eprintln!("| --no-crt0 | leave out the startup code, for bare-metal images               |");
+    eprintln!("| --entry | symbol the built-in startup code calls - defaults to main         |");
+    eprintln!("| Exit codes:                                                                 |");
+    eprintln!("| 1: invalid arguments, 2: file error, 3: syntax error, 4: linking error,     |");
+    eprintln!("| 5: simulated program failed or did not halt                                 |");
+    eprintln!("-------------------------------------------------------------------------------");
 }
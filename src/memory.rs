@@ -0,0 +1,163 @@
+use crate::{DATA_ADDRESS_OFFSET, TEXT_ADDRESS_OFFSET};
+use serde::Deserialize;
+
+/// # Memory map
+///
+/// where the segments of a program live on the target
+///
+/// bases and sizes are in bytes and have to be word aligned.
+/// The stack grows down from stack_top.
+/// physical_data_offset is the word address the target remaps data_base to,
+/// which is where print_hex places the data segment.
+/// text_depth and data_depth are the number of words in the block RAMs
+/// holding each segment, files for them are padded up to it.
+/// Left out, a memory is as deep as the program needs
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemoryMap {
+    pub text_base: u32,
+    pub text_size: u32,
+    pub data_base: u32,
+    pub data_size: u32,
+    pub stack_top: u32,
+    pub physical_data_offset: u32,
+    pub text_depth: Option<u32>,
+    pub data_depth: Option<u32>,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        MemoryMap {
+            text_base: TEXT_ADDRESS_OFFSET,
+            text_size: DATA_ADDRESS_OFFSET - TEXT_ADDRESS_OFFSET,
+            data_base: DATA_ADDRESS_OFFSET,
+            data_size: 0x8000_0000 - DATA_ADDRESS_OFFSET,
+            stack_top: 0x8000_0000,
+            physical_data_offset: 0x400,
+            text_depth: None,
+            data_depth: None,
+        }
+    }
+}
+
+/// # Memory map errors
+///
+/// everything that can make a memory map unusable
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MemoryMapError {
+    Syntax(String),
+    Unaligned(&'static str),
+    OutOfRange(&'static str),
+    Overlapping,
+}
+
+impl std::fmt::Display for MemoryMapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Syntax(e) => write!(f, "Invalid memory map: {e}"),
+            Self::Unaligned(field) => write!(f, "Memory map {field} is not a multiple of 4"),
+            Self::OutOfRange(segment) => {
+                write!(
+                    f,
+                    "Memory map {segment} segment reaches past the end of memory"
+                )
+            }
+            Self::Overlapping => write!(f, "Memory map text and data segments overlap"),
+        }
+    }
+}
+
+impl MemoryMap {
+    /// read a memory map from TOML, fields left out keep their default values
+    ///
+    /// ```toml
+    /// text_base = 0x0
+    /// data_base = 0x1000_0000
+    /// stack_top = 0x1000_8000
+    /// ```
+    pub fn from_toml(config: &str) -> Result<Self, MemoryMapError> {
+        let memory: MemoryMap =
+            toml::from_str(config).map_err(|e| MemoryMapError::Syntax(e.message().to_string()))?;
+        memory.validate()?;
+        Ok(memory)
+    }
+
+    /// check that the segments are word aligned and fit in memory without overlapping
+    pub fn validate(&self) -> Result<(), MemoryMapError> {
+        let fields = [
+            ("text_base", self.text_base),
+            ("text_size", self.text_size),
+            ("data_base", self.data_base),
+            ("data_size", self.data_size),
+            ("stack_top", self.stack_top),
+        ];
+        if let Some((field, _)) = fields.iter().find(|(_, value)| value % 4 != 0) {
+            return Err(MemoryMapError::Unaligned(field));
+        }
+
+        let (text, data) = (self.text(), self.data());
+        if text.end > 1 << 32 {
+            return Err(MemoryMapError::OutOfRange("text"));
+        }
+        if data.end > 1 << 32 {
+            return Err(MemoryMapError::OutOfRange("data"));
+        }
+        if text.start < data.end && data.start < text.end {
+            return Err(MemoryMapError::Overlapping);
+        }
+
+        Ok(())
+    }
+
+    /// byte addresses taken up by the text segment
+    pub fn text(&self) -> std::ops::Range<u64> {
+        self.text_base as u64..self.text_base as u64 + self.text_size as u64
+    }
+
+    /// byte addresses taken up by the data segment
+    pub fn data(&self) -> std::ops::Range<u64> {
+        self.data_base as u64..self.data_base as u64 + self.data_size as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // tests if fields left out keep their defaults and broken maps are rejected
+    #[test]
+    fn from_toml() {
+        assert_eq!(MemoryMap::from_toml(""), Ok(MemoryMap::default()));
+        assert_eq!(
+            MemoryMap::from_toml(
+                "text_size = 0x2000\ndata_base = 0x2000\ndata_size = 0x1000\nstack_top = 0x4000\n\
+                 data_depth = 1024\n"
+            ),
+            Ok(MemoryMap {
+                text_size: 0x2000,
+                data_depth: Some(1024),
+                data_base: 0x2000,
+                data_size: 0x1000,
+                stack_top: 0x4000,
+                ..MemoryMap::default()
+            })
+        );
+
+        assert_eq!(
+            MemoryMap::from_toml("data_base = 0x2000"),
+            Err(MemoryMapError::Overlapping)
+        );
+        assert_eq!(
+            MemoryMap::from_toml("data_base = 0x1000_0002"),
+            Err(MemoryMapError::Unaligned("data_base"))
+        );
+        assert_eq!(
+            MemoryMap::from_toml("data_base = 0xffff_0000"),
+            Err(MemoryMapError::OutOfRange("data"))
+        );
+        assert!(matches!(
+            MemoryMap::from_toml("data_bse = 0x2000"),
+            Err(MemoryMapError::Syntax(_))
+        ));
+    }
+}
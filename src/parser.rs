@@ -1,6 +1,7 @@
 use core::fmt;
-use lexgen_util::LexerError;
-use std::convert::Infallible;
+use lexgen_util::{LexerError, LexerErrorKind};
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 
 // helper functions for the parser
 mod parserhelpers;
@@ -14,148 +15,398 @@ use parseinstruction::*;
 mod parsedata;
 use parsedata::*;
 
+// parser for .equ and .set constant definitions
+mod parseconstant;
+use parseconstant::*;
+
+// parser for .align and .org placement directives
+mod parseplacement;
+use parseplacement::*;
+
+// parser for expressions used as operands
+mod parseexpression;
+use parseexpression::*;
+
 // lexer for the pierogi assembler language
 pub mod mylexer;
 
+use crate::expr::{Expr, ExprError, Op};
+use crate::source::{SourceFile, Span};
 use crate::types::*;
 
-use super::{DATA_ADDRESS_OFFSET, TEXT_ADDRESS_OFFSET};
+use super::MemoryMap;
 
-type ParserResult = Result<(Loc, Token), ParserError>;
+type ParserResult = Result<(Span, Token), ParserError>;
 
-/// # Location
-/// indicates where in the file a token/error was encountered
-#[derive(Debug, PartialEq)]
-pub struct Loc {
-    pub row: u32,
-    pub col: u32,
-}
+/// constants defined so far, by name
+pub type Constants = HashMap<String, ConstantNode>;
+
+/// the first address past the end of the address space, where every segment ends at the latest
+const ADDRESS_SPACE_END: u64 = 1 << 32;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ParserError {
-    CodeOutsideSegment(Loc),
-    InvalidToken(Loc),
-    Incomplete(Loc),
-    End,
+    CodeOutsideSegment(Span),
+    InvalidToken(Span),
+    Incomplete(Span),
+    End(Span),
     Empty,
-    NegativeSpace(Loc),
+    NegativeSpace(Span),
+    ImmediateOutOfRange(i64, RangeInclusive<i64>, Span),
+    LiteralOverflow(Span),
+    AddressOverflow(Span),
+    UndefinedConstant(String, Span),
+    InvalidExpression(ExprError, Span),
+    UnalignedAddress(Span),
+    NestingTooDeep(Span),
+    LabelledDirective(Span),
+    SegmentFull(Span),
+}
+
+impl ParserError {
+    /// location of the error in the source files
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::CodeOutsideSegment(span)
+            | Self::InvalidToken(span)
+            | Self::Incomplete(span)
+            | Self::End(span)
+            | Self::NegativeSpace(span)
+            | Self::LiteralOverflow(span)
+            | Self::AddressOverflow(span)
+            | Self::UndefinedConstant(_, span)
+            | Self::InvalidExpression(_, span)
+            | Self::UnalignedAddress(span)
+            | Self::NestingTooDeep(span)
+            | Self::LabelledDirective(span)
+            | Self::SegmentFull(span)
+            | Self::ImmediateOutOfRange(_, _, span) => Some(*span),
+            Self::Empty => None,
+        }
+    }
 }
 
 impl std::fmt::Display for ParserError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::CodeOutsideSegment(_loc) => write!(f, "Code outside of segment annotation"),
-            Self::InvalidToken(_loc) => {
+            Self::CodeOutsideSegment(_span) => write!(f, "Code outside of segment annotation"),
+            Self::InvalidToken(_span) => {
                 write!(f, "Invalid token")
             }
-            Self::Incomplete(_loc) => {
+            Self::Incomplete(_span) => {
                 write!(f, "Invalid statement")
             }
-            Self::End => write!(f, "End of input reached prematurely"),
+            Self::End(_span) => write!(f, "End of input reached prematurely"),
             Self::Empty => write!(f, "No valid tokens found"),
-            Self::NegativeSpace(_loc) => write!(f, "Number cannot be negative"),
+            Self::NegativeSpace(_span) => write!(f, "Number cannot be negative"),
+            Self::LiteralOverflow(_span) => write!(f, "Number too large"),
+            Self::AddressOverflow(_span) => {
+                write!(f, "Statement does not fit in the address space")
+            }
+            Self::UndefinedConstant(id, _span) => {
+                write!(f, "Constant not defined before use: \"{id}\"")
+            }
+            Self::InvalidExpression(e, _span) => write!(f, "{e}"),
+            Self::UnalignedAddress(_span) => write!(f, "Address is not a multiple of 4"),
+            Self::SegmentFull(_span) => write!(f, "No space left in segment"),
+            Self::LabelledDirective(_span) => {
+                write!(f, "Labels cannot be placed on .equ, .set, .org or .align")
+            }
+            Self::NestingTooDeep(_span) => write!(
+                f,
+                "Expression nested more than {MAX_EXPRESSION_DEPTH} levels deep"
+            ),
+            Self::ImmediateOutOfRange(imm, range, _span) => write!(
+                f,
+                "Immediate out of range: {imm} does not lie between {} and {}",
+                range.start(),
+                range.end()
+            ),
         }
     }
 }
 
 /// parse a program listing, creating an abstract program tree
 /// with two branches (data and instructions).
-pub fn parse(input_buffer: &str) -> Result<ProgramTree, ParserError> {
+///
+/// parsing continues after an error, so every error in the listing is returned
+pub fn parse(input_buffer: &str) -> Result<ProgramTree, Vec<ParserError>> {
+    let (tree, errors) =
+        parse_with_recovery(&[SourceFile::new("", input_buffer)], &MemoryMap::default());
+
+    if errors.is_empty() {
+        Ok(tree)
+    } else {
+        Err(errors)
+    }
+}
+
+/// # Parser state
+///
+/// everything carried over from one statement to the next
+struct ParserState {
+    tree: ProgramTree,
+    segment: Option<Bl>,
+    data_address: u64,
+    instr_address: u64,
+    /// placement directives in the text segment since the last instruction
+    placement: Option<Placement>,
+    constants: Constants,
+    memory: MemoryMap,
+}
+
+/// parse the source files of a program, in order,
+/// resynchronising at the next line or instruction after an error
+///
+/// segments start at the bases given in memory.
+/// Returns the tree built from all statements that parsed correctly, along with all errors found
+pub fn parse_with_recovery(
+    sources: &[SourceFile],
+    memory: &MemoryMap,
+) -> (ProgramTree, Vec<ParserError>) {
+    let mut errors = Vec::new();
+
     // create a program tree structure to output
-    let mut tree = ProgramTree {
+    let tree = ProgramTree {
         instructions: Vec::new(),
         data: Vec::new(),
+        constants: Vec::new(),
     };
 
-    let mut current_segment;
-
     // create a lexer iterator to recognize tokens in the input
-    let mut lexer = mylexer::Lexer::new(input_buffer);
+    let mut lexer = Lexer::from_sources(sources);
 
     // the first segment annotation has to be treated separately
-    current_segment = match lexer.next() {
-        None => return Err(ParserError::Empty),
-        Some(Ok((_, Token::Block(Bl::Data), _))) => Bl::Data,
-        Some(Ok((_, Token::Block(Bl::Text), _))) => Bl::Text,
-        Some(Ok((l, _, _))) => {
-            return Err(ParserError::CodeOutsideSegment(Loc {
-                row: l.line,
-                col: l.col,
-            }))
+    let segment = match read_token(&mut lexer) {
+        Err(ParserError::End(_)) => {
+            errors.push(ParserError::Empty);
+            return (tree, errors);
+        }
+        Ok((_, Token::Block(Bl::Data))) => Some(Bl::Data),
+        Ok((_, Token::Block(Bl::Text))) => Some(Bl::Text),
+        // constants do not belong to a segment, so they can come first
+        Ok((_, Token::Block(Bl::Equ | Bl::Set))) => {
+            lexer.step_back();
+            None
         }
-        Some(Err(LexerError {
-            location: l,
-            kind: _,
-        })) => {
-            return Err(ParserError::InvalidToken(Loc {
-                row: l.line,
-                col: l.col,
-            }))
+        first => {
+            errors.push(match first {
+                Ok((span, _)) => ParserError::CodeOutsideSegment(span),
+                Err(e) => e,
+            });
+
+            // everything up to the first segment annotation is outside of a segment
+            loop {
+                match lexer.next() {
+                    None => return (tree, errors),
+                    Some(Ok((_, Token::Block(Bl::Data)))) => break Some(Bl::Data),
+                    Some(Ok((_, Token::Block(Bl::Text)))) => break Some(Bl::Text),
+                    Some(_) => (),
+                }
+            }
         }
     };
 
-    let mut data_address = DATA_ADDRESS_OFFSET;
-    // first two instructions initialize the stack and global pointers
-    let mut instr_address = TEXT_ADDRESS_OFFSET;
-    let mut identifier;
+    let mut state = ParserState {
+        tree,
+        segment,
+        data_address: memory.data_base as u64,
+        instr_address: memory.text_base as u64,
+        placement: None,
+        constants: Constants::new(),
+        memory: memory.clone(),
+    };
 
-    // loop over the input until your reach an error or its end
-    'outer: loop {
-        let token = read_token(lexer.next());
+    let mut leading = Span::default();
 
-        // exit loop on end of stream
-        let (mut loc, mut token) = match token {
-            Err(ParserError::End) => break 'outer,
-            Ok(t) => t,
-            Err(e) => return Err(e),
-        };
+    // loop over the input until you reach its end
+    while lexer.peek().is_some() {
+        match parse_statement(&mut lexer, &mut state, &mut leading) {
+            Ok(()) => (),
+            Err(e @ ParserError::End(_)) => {
+                errors.push(e);
+                break;
+            }
+            Err(e) => {
+                errors.push(e);
+                resynchronise(&mut lexer, leading);
+            }
+        }
+    }
+
+    (state.tree, errors)
+}
+
+/// parse one statement: an optional identifier, followed by a segment annotation,
+/// a constant definition, a data block or an instruction
+///
+/// leading is set to the span of the token following the identifier
+fn parse_statement(
+    lexer: &mut Lexer<'_>,
+    state: &mut ParserState,
+    leading: &mut Span,
+) -> Result<(), ParserError> {
+    let (mut span, mut token) = read_token(lexer)?;
+    *leading = span;
+
+    // read identifier if present, remembering where it was defined
+    let (identifier, label_span) = if let Token::Identifier(str) = token {
+        sel_token(lexer, Token::Colon)?;
+
+        let label_span = span;
+        (span, token) = read_token(lexer)?;
+        *leading = span;
+        (str, label_span)
+    } else {
+        (String::new(), Span::default())
+    };
+
+    // constants can be defined in either segment
+    if let Token::Block(Bl::Equ | Bl::Set) = token {
+        // they are not tied to an address, so they cannot be labelled
+        if !identifier.is_empty() {
+            return Err(ParserError::LabelledDirective(label_span));
+        }
 
-        // read identifier if present
-        if let Token::Identifier(str) = token {
-            identifier = str;
-            sel_token(lexer.next(), Token::Colon)?;
+        let redefinable = token == Token::Block(Bl::Set);
+        let node = parse_constant(redefinable, span, lexer, &mut state.constants)?;
+        state.tree.constants.push(node);
+        return Ok(());
+    }
 
-            (loc, token) = read_token(lexer.next())?;
-        } else {
-            identifier = String::new();
+    // segment annotations switch to their segment wherever they appear
+    if let Token::Block(segment @ (Bl::Data | Bl::Text)) = token {
+        state.segment = Some(segment);
+        return Ok(());
+    }
+
+    // placement directives move the address of the current segment.
+    // They are not tied to a statement, so they cannot be labelled
+    if let Token::Block(block @ (Bl::Align | Bl::Org)) = token {
+        if !identifier.is_empty() {
+            return Err(ParserError::LabelledDirective(label_span));
         }
 
-        match current_segment {
-            Bl::Data => {
-                match token {
-                    Token::Block(Bl::Text) => current_segment = Bl::Text,
-                    Token::Block(Bl::Data) => continue,
-                    Token::Block(b) => {
-                        // if inside data segment: try to parse one data line
-                        let node = parse_data(b, identifier, &mut lexer, &mut data_address)?;
-                        tree.data.push(node);
+        // the address has to stay within the segment
+        let (address, segment) = match state.segment {
+            Some(Bl::Data) => (&mut state.data_address, state.memory.data()),
+            Some(Bl::Text) => (&mut state.instr_address, state.memory.text()),
+            _ => return Err(ParserError::CodeOutsideSegment(span)),
+        };
+        let range = segment.start as i64..=segment.end as i64 - 4;
+        let placement = parse_placement(block, lexer, &state.constants, address, range)?;
+
+        // the next instruction remembers where it was placed, in case relaxation moves the code.
+        // An address fixed with .org is still fixed after aligning it
+        if state.segment == Some(Bl::Text) {
+            state.placement = match (state.placement, placement) {
+                (Some(Placement::Org(_)), _) | (_, Placement::Org(_)) => {
+                    Some(Placement::Org(state.instr_address as u32))
+                }
+                (Some(Placement::Align(a)), Placement::Align(b)) => {
+                    Some(Placement::Align(a.max(b)))
+                }
+                (None, align) => Some(align),
+            };
+        }
+        return Ok(());
+    }
+
+    match state.segment {
+        Some(Bl::Data) => {
+            match token {
+                Token::Block(b) => {
+                    // if inside data segment: try to parse one data line
+                    let mut nodes = parse_data(
+                        b,
+                        identifier,
+                        span,
+                        lexer,
+                        &state.constants,
+                        &mut state.data_address,
+                    )?;
+                    if let Some(first) = nodes.first_mut() {
+                        first.label_span = label_span;
                     }
-                    _ => return Err(ParserError::Incomplete(loc)),
+                    state.tree.data.append(&mut nodes);
                 }
+                _ => return Err(ParserError::Incomplete(span)),
             }
-            Bl::Text => {
-                match token {
-                    Token::Block(Bl::Data) => current_segment = Bl::Data,
-                    Token::Block(Bl::Text) => continue,
-                    Token::Instruction(i) => {
-                        // if inside text segment: try to parse one instruction
-                        let mut nodes =
-                            parse_instruction(i, identifier, &mut lexer, &mut instr_address)?;
-                        tree.instructions.append(&mut nodes);
+        }
+        Some(Bl::Text) => {
+            match token {
+                Token::Instruction(i) => {
+                    // if inside text segment: try to parse one instruction
+                    let mut nodes = parse_instruction(
+                        i,
+                        identifier,
+                        span,
+                        lexer,
+                        &state.constants,
+                        &mut state.instr_address,
+                    )?;
+                    if let Some(first) = nodes.first_mut() {
+                        first.label_span = label_span;
+                        first.placement = state.placement.take();
                     }
-                    _ => return Err(ParserError::Incomplete(loc)),
+                    state.tree.instructions.append(&mut nodes);
                 }
+                _ => return Err(ParserError::Incomplete(span)),
             }
-            _ => return Err(ParserError::CodeOutsideSegment(loc)),
         }
+        _ => return Err(ParserError::CodeOutsideSegment(span)),
     }
 
-    Ok(tree)
+    Ok(())
+}
+
+/// # Resynchronise
+///
+/// after an error, skip ahead to the start of the next statement:
+/// the next instruction or segment annotation, or the first token on the next line
+fn resynchronise(lexer: &mut Lexer<'_>, leading: Span) {
+    let (file, row) = match lexer.last_token() {
+        // the token that caused the error starts a new statement, so it is read again
+        Some(Ok((span, Token::Instruction(_) | Token::Block(_)))) if *span != leading => {
+            lexer.step_back();
+            return;
+        }
+        Some(Ok((span, Token::Identifier(_))))
+            if (span.file, span.row) > (leading.file, leading.row) =>
+        {
+            lexer.step_back();
+            return;
+        }
+        Some(Ok((span, _)))
+        | Some(Err(ParserError::InvalidToken(span) | ParserError::LiteralOverflow(span))) => {
+            (span.file, span.row)
+        }
+        _ => return,
+    };
+
+    // skip the rest of the line
+    loop {
+        let skip = match lexer.peek() {
+            None => false,
+            Some(Ok((_, Token::Instruction(_) | Token::Block(_)))) => false,
+            Some(Ok((span, _)))
+            | Some(Err(ParserError::InvalidToken(span) | ParserError::LiteralOverflow(span))) => {
+                (span.file, span.row) == (file, row)
+            }
+            Some(Err(_)) => false,
+        };
+
+        if !skip {
+            break;
+        }
+        lexer.next();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{DATA_ADDRESS_OFFSET, TEXT_ADDRESS_OFFSET};
 
     #[test]
     fn parsetest() {
@@ -173,20 +424,49 @@ mod tests {
         assert_eq!(
             tree,
             ProgramTree {
+                constants: vec![],
                 data: vec![
                     DataNode {
                         identifier: "buffer".to_string(),
+                        label_span: Span {
+                            file: 0,
+                            row: 2,
+                            col: 12,
+                            len: 6
+                        },
                         address: DATA_ADDRESS_OFFSET,
                         block: Bl::Space,
                         data: 0,
+                        data_expr: None,
+                        payload: vec![],
                         num: 4,
+                        span: Span {
+                            file: 0,
+                            row: 2,
+                            col: 20,
+                            len: 8
+                        },
                     },
                     DataNode {
                         identifier: "number".to_string(),
+                        label_span: Span {
+                            file: 0,
+                            row: 3,
+                            col: 12,
+                            len: 6
+                        },
                         address: DATA_ADDRESS_OFFSET + 16,
                         block: Bl::Word,
                         data: 5,
+                        data_expr: None,
+                        payload: vec![],
                         num: 1,
+                        span: Span {
+                            file: 0,
+                            row: 3,
+                            col: 20,
+                            len: 7
+                        },
                     }
                 ],
                 instructions: vec![
@@ -198,8 +478,21 @@ mod tests {
                         rb: 0,
                         imm: 0,
                         identifier: "main".to_string(),
-                        imm_identifier: "".to_string(),
+                        label_span: Span {
+                            file: 0,
+                            row: 5,
+                            col: 12,
+                            len: 4
+                        },
+                        imm_expr: None,
                         address: TEXT_ADDRESS_OFFSET,
+                        placement: None,
+                        span: Span {
+                            file: 0,
+                            row: 6,
+                            col: 12,
+                            len: 20
+                        },
                     },
                     InstructionNode {
                         op: Instr::Beq,
@@ -208,11 +501,90 @@ mod tests {
                         rb: 0,
                         imm: 0,
                         identifier: "".to_string(),
-                        imm_identifier: "main".to_string(),
+                        label_span: Span::default(),
+                        imm_expr: Some(Expr::Identifier("main".to_string())),
                         address: TEXT_ADDRESS_OFFSET + 4,
+                        placement: None,
+                        span: Span {
+                            file: 0,
+                            row: 7,
+                            col: 12,
+                            len: 19
+                        },
                     },
                 ],
             }
         );
     }
+
+    #[test]
+    fn recovery() {
+        let input = "
+        .text
+            add $1, $2 add $3, $4, $5
+            addi $1, $1, $1 $1
+            lw $1,
+            loop: sw $1, $2
+        .data
+            .word @
+            x: .space -1
+            y: .word 4";
+
+        let (tree, errors) =
+            parse_with_recovery(&[SourceFile::new("", input)], &MemoryMap::default());
+
+        assert_eq!(
+            errors,
+            vec![
+                ParserError::Incomplete(Span {
+                    file: 0,
+                    row: 2,
+                    col: 23,
+                    len: 3
+                }),
+                ParserError::Incomplete(Span {
+                    file: 0,
+                    row: 3,
+                    col: 25,
+                    len: 2
+                }),
+                ParserError::Incomplete(Span {
+                    file: 0,
+                    row: 5,
+                    col: 12,
+                    len: 4
+                }),
+                ParserError::InvalidToken(Span {
+                    file: 0,
+                    row: 7,
+                    col: 18,
+                    len: 1
+                }),
+                ParserError::NegativeSpace(Span {
+                    file: 0,
+                    row: 8,
+                    col: 22,
+                    len: 2
+                }),
+            ]
+        );
+
+        // statements following the errors are still parsed
+        let ops: Vec<Instr> = tree.instructions.iter().map(|n| n.op.clone()).collect();
+        assert_eq!(ops, vec![Instr::Add, Instr::Sw]);
+        assert_eq!(tree.instructions[0].rd, 3);
+        assert_eq!(tree.instructions[1].identifier, "loop");
+        assert_eq!(tree.data.len(), 1);
+        assert_eq!(tree.data[0].identifier, "y");
+
+        assert_eq!(
+            parse(".data\n.word"),
+            Err(vec![ParserError::End(Span {
+                file: 0,
+                row: 1,
+                col: 5,
+                len: 0
+            })])
+        );
+    }
 }
@@ -1,9 +1,72 @@
-use crate::{Bl, Instr, Token};
+use crate::{Bl, Instr, Op, Token};
 use lexgen::lexer;
+use std::num::{IntErrorKind, ParseIntError};
+
+/// # Literal errors
+///
+/// raised when a numeric literal cannot be converted to a value,
+/// or a string literal holds an unknown escape sequence
+///
+/// both hold the length of the literal, since the error is located at its end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LiteralError {
+    Overflow(u32),
+    Invalid(u32),
+}
+
+impl LiteralError {
+    fn new(e: ParseIntError, literal: &str) -> Self {
+        let len = literal.chars().count() as u32;
+        match e.kind() {
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => LiteralError::Overflow(len),
+            _ => LiteralError::Invalid(len),
+        }
+    }
+}
+
+/// convert the contents of a string literal, including its quotes, to the bytes it stands for
+///
+/// supported escape sequences are \n, \t, \r, \0, \\, \", \' and \xHH
+fn unescape(literal: &str) -> Result<Vec<u8>, LiteralError> {
+    let invalid = || LiteralError::Invalid(literal.chars().count() as u32);
+
+    let mut bytes = Vec::new();
+    let mut chars = literal[1..literal.len() - 1].chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+
+        bytes.push(match chars.next().ok_or_else(invalid)? {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            '0' => 0,
+            '\\' => b'\\',
+            '"' => b'"',
+            '\'' => b'\'',
+            'x' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 => byte,
+                    _ => return Err(invalid()),
+                }
+            }
+            _ => return Err(invalid()),
+        });
+    }
+
+    Ok(bytes)
+}
 
 lexer! {
     pub Lexer -> Token;
 
+    type Error = LiteralError;
+
     let whitespace = [' ' '\t' '\n'] | "\r\n";
     let alphanumeric = ['a'-'z' 'A'-'Z' '0'-'9' '_'];
 
@@ -15,6 +78,26 @@ lexer! {
 
         '#' => |lexer| lexer.switch(LexerRule::Comment), // hash starts a comment
 
+        // operators and parentheses used in expressions
+        '+'   = Token::Operator(Op::Add),
+        '-'   = Token::Operator(Op::Sub),
+        '*'   = Token::Operator(Op::Mul),
+        '/'   = Token::Operator(Op::Div),
+        '%'   = Token::Operator(Op::Rem),
+        "<<"  = Token::Operator(Op::Shl),
+        ">>"  = Token::Operator(Op::Shr),
+        '&'   = Token::Operator(Op::And),
+        '|'   = Token::Operator(Op::Or),
+        '^'   = Token::Operator(Op::Xor),
+        '~'   = Token::Operator(Op::Not),
+        // relocation operators, taking the halves of an address
+        "%hi"  > ((_ # $alphanumeric)|$) = Token::Operator(Op::Hi),
+        "%lo"  > ((_ # $alphanumeric)|$) = Token::Operator(Op::Lo),
+        "%hia" > ((_ # $alphanumeric)|$) = Token::Operator(Op::Hia),
+
+        '('   = Token::LParen,
+        ')'   = Token::RParen,
+
         // match instruction names if not followed by an alphanumeric characater
         "and" > ((_ # $alphanumeric)|$) = Token::Instruction(Instr::And),
         "or"  > ((_ # $alphanumeric)|$) = Token::Instruction(Instr::Or),
@@ -69,22 +152,28 @@ lexer! {
         // grabbing and parsing a hex number
         // match if not followed by an alphanumeric characater
         let hexdigit = ['a'-'f' 'A'-'F' '0' - '9'];
-        "0x" $hexdigit+ > ((_ # ['g'-'z' 'G'-'Z' '_'])|$)=> |lexer| {
+        "0x" $hexdigit+ > ((_ # ['g'-'z' 'G'-'Z' '_'])|$) =? |lexer| {
             let contents = lexer.match_();
             let stripped = &contents[2..contents.len()];
-            match i64::from_str_radix(stripped, 16) {
-                Ok(n) => lexer.return_(Token::Immediate(n)),
-                Err(_) => lexer.return_(Token::Err),
-            }
+            let value = i64::from_str_radix(stripped, 16);
+            lexer.return_(value.map(Token::Immediate).map_err(|e| LiteralError::new(e, contents)))
         },
 
-        // grabbing a decimal number
+        // grabbing a decimal number, underscores can be used to separate digits
         // match if not followed by an alphanumeric characater
+        // the sign is not part of the number, it is read as an operator
         let digit = ['0'-'9' '_'];
 
-        ['+' '-']? $digit+ > ((_ # $id_init)|$) => |lexer| {
-            let contents = lexer.match_().parse::<i64>().unwrap();
-            lexer.return_(Token::Immediate(contents))
+        $digit+ > ((_ # $id_init)|$) =? |lexer| {
+            let contents = lexer.match_();
+            let value = contents.replace('_', "").parse::<i64>();
+            lexer.return_(value.map(Token::Immediate).map_err(|e| LiteralError::new(e, contents)))
+        },
+
+        // grabbing a string, a backslash escapes the character after it
+        '"' ((_ # ['"' '\\' '\n']) | ('\\' (_ # '\n')))* '"' =? |lexer| {
+            let value = unescape(lexer.match_());
+            lexer.return_(value.map(Token::String))
         },
 
         // match if not followed by an alphanumeric characater
@@ -93,6 +182,14 @@ lexer! {
         ".word" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Word), // word block starts with .word
         ".space" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Space), // space block starts with .space
         ".addr" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Addr), // address starts with .addr
+        ".equ" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Equ), // constant starts with .equ
+        ".set" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Set), // redefinable constant starts with .set
+        ".ascii" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Ascii), // string starts with .ascii
+        ".asciiz" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Asciiz), // terminated string starts with .asciiz
+        ".byte" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Byte), // byte list starts with .byte
+        ".half" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Half), // halfword list starts with .half
+        ".align" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Align), // padding starts with .align
+        ".org" > ((_ # $alphanumeric)|$) = Token::Block(Bl::Org), // fixed placement starts with .org
 
     }
 
@@ -187,11 +284,11 @@ mod tests {
         assert_eq!(vec1, output);
     }
 
-    // test supported immediate types: signed decimal, unsigned hex
+    // test supported immediate types: decimal, hex
     #[test]
     fn immediate() {
-        let input = "0 +0 -0 010 +010 -010 0xdeadbeef 0x7fffffffffffffff";
-        let ovec: Vec<i64> = vec![0, 0, 0, 10, 10, -10, 0xdeadbeef, 0x7fffffffffffffff];
+        let input = "0 010 1_000 0xdeadbeef 0x7fffffffffffffff";
+        let ovec: Vec<i64> = vec![0, 10, 1000, 0xdeadbeef, 0x7fffffffffffffff];
 
         let ivec: Vec<i64> = Lexer::new(input)
             .map(|res| match res {
@@ -202,6 +299,27 @@ mod tests {
         assert_eq!(ovec, ivec);
     }
 
+    // test string literals and their escape sequences
+    #[test]
+    fn strings() {
+        let input = r#""Hi!\n" "" "tab\there" "\x41\0\\\"" "bad\q" "\x4""#;
+        let results: Vec<Result<Token, LexerErrorKind<LiteralError>>> = Lexer::new(input)
+            .map(|res| res.map(|(_, t, _)| t).map_err(|e| e.kind))
+            .collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Ok(Token::String(b"Hi!\n".to_vec())),
+                Ok(Token::String(vec![])),
+                Ok(Token::String(b"tab\there".to_vec())),
+                Ok(Token::String(b"A\0\\\"".to_vec())),
+                Err(LexerErrorKind::Custom(LiteralError::Invalid(7))),
+                Err(LexerErrorKind::Custom(LiteralError::Invalid(5))),
+            ]
+        );
+    }
+
     // test supported block types
     #[test]
     fn blocks() {
@@ -232,7 +350,7 @@ mod tests {
     #[test]
     fn garbled() {
         let malformed_strings = [
-            "&t0", "+ěš", "$t4", "$one", ".home", "[", "𝝀", ".data1", "$t255",
+            "@t0", "ěš", "$t4", "$one", ".home", "[", "𝝀", ".data1", "$t255",
         ];
 
         for string in malformed_strings {
@@ -275,4 +393,25 @@ mod tests {
         );
         assert_eq!(lexer.next(), None);
     }
+
+    // test literals that do not fit, underscores alone make an identifier
+    #[test]
+    fn literal_errors() {
+        let input = "1_000 99999999999999999999 999999999999999999999 0x10000000000000000 _1 0x7fffffffffffffff";
+        let results: Vec<Result<Token, LexerErrorKind<LiteralError>>> = Lexer::new(input)
+            .map(|res| res.map(|(_, t, _)| t).map_err(|e| e.kind))
+            .collect();
+
+        assert_eq!(
+            results,
+            vec![
+                Ok(Token::Immediate(1000)),
+                Err(LexerErrorKind::Custom(LiteralError::Overflow(20))),
+                Err(LexerErrorKind::Custom(LiteralError::Overflow(21))),
+                Err(LexerErrorKind::Custom(LiteralError::Overflow(19))),
+                Ok(Token::Identifier("_1".to_string())),
+                Ok(Token::Immediate(0x7fffffffffffffff)),
+            ]
+        );
+    }
 }
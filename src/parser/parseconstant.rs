@@ -0,0 +1,90 @@
+use super::parserhelpers::*;
+use super::*;
+
+/// given the block identifier of a constant definition, parse the rest of it and return the node
+///
+/// form: .equ identifier, immediate (or .set)
+///
+/// the value is recorded in constants, unless the identifier is already taken
+/// by a constant that cannot be redefined. The conflict is reported by the linker
+pub fn parse_constant(
+    redefinable: bool,
+    span: Span,
+    lexer: &mut Lexer<'_>,
+    constants: &mut Constants,
+) -> Result<ConstantNode, ParserError> {
+    let identifier = match read_token(lexer)? {
+        (_, Token::Identifier(str)) => str,
+        (span, _) => return Err(ParserError::Incomplete(span)),
+    };
+    sel_token(lexer, Token::Comma)?;
+    let value = get_immediate(lexer, constants, WORD_RANGE)?;
+
+    let node = ConstantNode {
+        identifier,
+        value,
+        redefinable,
+        span: span.to(lexer.end()),
+    };
+
+    match constants.get(&node.identifier) {
+        Some(previous) if !(previous.redefinable && redefinable) => (),
+        _ => {
+            constants.insert(node.identifier.clone(), node.clone());
+        }
+    }
+
+    Ok(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant() {
+        let mut constants = Constants::new();
+
+        let mut lexer = Lexer::new("SIZE, 0x10");
+        let node = parse_constant(false, Span::default(), &mut lexer, &mut constants).unwrap();
+        assert_eq!(
+            node,
+            ConstantNode {
+                identifier: "SIZE".to_string(),
+                value: 16,
+                redefinable: false,
+                span: Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 10
+                },
+            }
+        );
+
+        // constants can be defined in terms of earlier ones, but .equ cannot be redefined
+        let mut lexer = Lexer::new("SIZE, SIZE");
+        assert!(parse_constant(false, Span::default(), &mut lexer, &mut constants).is_ok());
+        assert_eq!(constants["SIZE"], node);
+
+        let mut lexer = Lexer::new("count, 1");
+        parse_constant(true, Span::default(), &mut lexer, &mut constants).unwrap();
+        let mut lexer = Lexer::new("count, SIZE");
+        parse_constant(true, Span::default(), &mut lexer, &mut constants).unwrap();
+        assert_eq!(constants["count"].value, 16);
+
+        let mut lexer = Lexer::new("other, missing");
+        assert_eq!(
+            parse_constant(true, Span::default(), &mut lexer, &mut constants),
+            Err(ParserError::UndefinedConstant(
+                "missing".to_string(),
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 7,
+                    len: 7
+                }
+            ))
+        );
+    }
+}
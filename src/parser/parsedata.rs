@@ -1,128 +1,724 @@
 use super::parserhelpers::*;
 use super::*;
 
-/// given the block identifier of a data node, parse the rest of it and return the node
+/// given the block identifier of a data node, parse the rest of it and return the nodes
+///
+/// returns a vector of nodes, since a .word list takes one node per element,
+/// the first one carrying the identifier
+///
+/// all nodes span from the block identifier to the end of the statement
 pub fn parse_data(
     block: Bl,
     identifier: String,
+    span: Span,
     lexer: &mut Lexer<'_>,
-    address: &mut u32,
-) -> Result<DataNode, ParserError> {
-    let mut data = 0;
+    constants: &Constants,
+    address: &mut u64,
+) -> Result<Vec<DataNode>, ParserError> {
+    let mut payload = Vec::new();
     let mut num: u32 = 1;
-    let mut addr = *address;
+    let mut addr = *address as u32;
 
     match block {
-        // form: .word immediate
+        // form: .word expression, expression : count ...
+        // each expression has to fit in a word, it is resolved by the linker if it uses labels.
+        // An expression followed by a count fills that many words
         Bl::Word => {
-            data = get_immediate(lexer.next())?;
-            *address += 4;
+            let mut nodes = Vec::new();
+            let mut identifier = identifier;
+
+            loop {
+                let (data_expr, data) = get_expression_or_imm(lexer, constants, WORD_RANGE)?;
+
+                let num = match lexer.peek() {
+                    Some(Ok((_, Token::Colon))) => {
+                        lexer.next();
+                        get_count(lexer, constants, *address)?
+                    }
+                    _ => 1,
+                };
+                let start = *address as u32;
+                reserve(span, lexer, address, num)?;
+
+                nodes.push(DataNode {
+                    identifier: std::mem::take(&mut identifier),
+                    label_span: Span::default(),
+                    address: start,
+                    block: Bl::Word,
+                    data,
+                    data_expr,
+                    payload: vec![],
+                    num,
+                    span,
+                });
+
+                if !next_in_list(lexer) {
+                    break;
+                }
+            }
+
+            // every node covers the whole statement it was parsed from
+            let span = span.to(lexer.end());
+            for node in &mut nodes {
+                node.span = span;
+            }
+
+            return Ok(nodes);
         }
-        // form: .word immediate
-        // immediate is restricted to positive values, since it represents a number of words
+        // form: .space immediate
         Bl::Space => {
-            if let (loc, Token::Immediate(i)) = read_token(lexer.next())? {
-                if i >= 0 {
-                    num = i as u32;
-                    *address += 4 * num;
-                } else {
-                    return Err(ParserError::NegativeSpace(loc));
-                }
-            };
+            num = get_count(lexer, constants, *address)?;
+            reserve(span, lexer, address, num)?;
         }
-        // form .word immediate
+        // form .addr immediate
         // immediate is restricted to positive values, since it represents a memory address
-        Bl::Addr => {
-            if let (loc, Token::Immediate(i)) = read_token(lexer.next())? {
-                if i >= 0 {
-                    addr = i as u32;
-                } else {
-                    return Err(ParserError::NegativeSpace(loc));
+        Bl::Addr => match get_value(lexer, constants)? {
+            (span, i) if i < 0 => return Err(ParserError::NegativeSpace(span)),
+            (span, i) if i > u32::MAX as i64 => {
+                return Err(ParserError::ImmediateOutOfRange(
+                    i,
+                    0..=u32::MAX as i64,
+                    span,
+                ))
+            }
+            (_, i) => addr = i as u32,
+        },
+        // form: .ascii "string", "string" ...
+        // form: .asciiz "string", "string" ...
+        // .asciiz terminates every string with a zero byte
+        Bl::Ascii | Bl::Asciiz => {
+            loop {
+                match read_token(lexer)? {
+                    (_, Token::String(bytes)) => payload.extend(bytes.into_iter().map(u32::from)),
+                    (span, _) => return Err(ParserError::Incomplete(span)),
+                }
+                if block == Bl::Asciiz {
+                    payload.push(0);
+                }
+                if !next_in_list(lexer) {
+                    break;
                 }
+            }
+            num = words(span, lexer, address, payload.len(), 4)?;
+        }
+        // form: .byte immediate, immediate ...
+        // form: .half immediate, immediate ...
+        // every immediate has to fit in a byte or a halfword, whether it is read as signed or not
+        Bl::Byte | Bl::Half => {
+            let (range, per_word) = match block {
+                Bl::Byte => (-0x80..=0xff, 4),
+                _ => (-0x8000..=0xffff, 2),
             };
+            let mask = *range.end() as u32;
+
+            loop {
+                payload.push(get_immediate(lexer, constants, range.clone())? as u32 & mask);
+                if !next_in_list(lexer) {
+                    break;
+                }
+            }
+            num = words(span, lexer, address, payload.len(), per_word)?;
         }
         _ => {
-            let (loc, _) = read_token(lexer.next())?;
-            return Err(ParserError::Incomplete(loc));
+            let (span, _) = read_token(lexer)?;
+            return Err(ParserError::Incomplete(span));
         }
     }
 
-    Ok(DataNode {
+    Ok(vec![DataNode {
         identifier,
+        label_span: Span::default(),
         address: addr,
         block,
-        data,
+        data: 0,
+        data_expr: None,
+        payload,
         num,
-    })
+        span: span.to(lexer.end()),
+    }])
+}
+
+/// read a number of words to fill
+///
+/// it is restricted to positive values,
+/// and the words have to fit in the rest of the address space after address
+fn get_count(
+    lexer: &mut Lexer<'_>,
+    constants: &Constants,
+    address: u64,
+) -> Result<u32, ParserError> {
+    let max = (ADDRESS_SPACE_END.saturating_sub(address) / 4) as i64;
+
+    match get_value(lexer, constants)? {
+        (span, i) if i < 0 => Err(ParserError::NegativeSpace(span)),
+        (span, i) if i > max && max == 0 => Err(ParserError::SegmentFull(span)),
+        (span, i) if i > max => Err(ParserError::ImmediateOutOfRange(i, 0..=max, span)),
+        (_, i) => Ok(i as u32),
+    }
+}
+
+/// consume the comma separating two list elements, returns whether there is another element
+fn next_in_list(lexer: &mut Lexer<'_>) -> bool {
+    let comma = matches!(lexer.peek(), Some(Ok((_, Token::Comma))));
+    if comma {
+        lexer.next();
+    }
+    comma
+}
+
+/// number of words taken up by len elements packed per_word to a word,
+/// moving address past them
+fn words(
+    span: Span,
+    lexer: &Lexer<'_>,
+    address: &mut u64,
+    len: usize,
+    per_word: usize,
+) -> Result<u32, ParserError> {
+    let num = len.div_ceil(per_word) as u32;
+    reserve(span, lexer, address, num)?;
+    Ok(num)
+}
+
+/// move address past num words, which have to end within the address space.
+/// Nothing can be placed once the address space is used up, not even an empty block
+fn reserve(span: Span, lexer: &Lexer<'_>, address: &mut u64, num: u32) -> Result<(), ParserError> {
+    let span = span.to(lexer.end());
+    if *address >= ADDRESS_SPACE_END {
+        return Err(ParserError::SegmentFull(span));
+    }
+
+    let end = *address + 4 * num as u64;
+    if end > ADDRESS_SPACE_END {
+        return Err(ParserError::AddressOverflow(span));
+    }
+    *address = end;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn word_list() {
+        let mut address = 0x10;
+        let mut lexer = Lexer::new("1, handler + 4, -1 : 1000 0 : -1");
+
+        let node = |address, data, data_expr, num| DataNode {
+            identifier: "".to_string(),
+            label_span: Span::default(),
+            address,
+            block: Bl::Word,
+            data,
+            data_expr,
+            payload: vec![],
+            num,
+            span: Span {
+                file: 0,
+                row: 0,
+                col: 0,
+                len: 25,
+            },
+        };
+
+        assert_eq!(
+            parse_data(
+                Bl::Word,
+                "table".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
+            vec![
+                DataNode {
+                    identifier: "table".to_string(),
+                    ..node(0x10, 1, None, 1)
+                },
+                node(
+                    0x14,
+                    0,
+                    Some(Expr::Binary(
+                        Op::Add,
+                        Box::new(Expr::Identifier("handler".to_string())),
+                        Box::new(Expr::Value(4))
+                    )),
+                    1
+                ),
+                node(0x18, -1, None, 1000),
+            ]
+        );
+
+        assert_eq!(address, 0x18 + 4 * 1000);
+
+        assert_eq!(
+            parse_data(
+                Bl::Word,
+                "".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::NegativeSpace(Span {
+                file: 0,
+                row: 0,
+                col: 30,
+                len: 2
+            }))
+        );
+    }
+
+    #[test]
+    fn strings_and_lists() {
+        let mut address = 0x10;
+        let mut lexer = Lexer::new("\"ab\", \"cde\" -1, 0x7f, 2 0x1234, -2");
+
+        assert_eq!(
+            parse_data(
+                Bl::Asciiz,
+                "message".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
+            vec![DataNode {
+                identifier: "message".to_string(),
+                label_span: Span::default(),
+                address: 0x10,
+                block: Bl::Asciiz,
+                data: 0,
+                data_expr: None,
+                payload: vec![0x61, 0x62, 0, 0x63, 0x64, 0x65, 0],
+                num: 2,
+                span: Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 11
+                },
+            }]
+        );
+
+        assert_eq!(
+            parse_data(
+                Bl::Byte,
+                "".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap()[0]
+                .payload,
+            vec![0xff, 0x7f, 2]
+        );
+
+        assert_eq!(
+            parse_data(
+                Bl::Half,
+                "".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap()[0]
+                .payload,
+            vec![0x1234, 0xfffe]
+        );
+
+        // two words of string, one of bytes and one of halfwords
+        assert_eq!(address, 0x10 + 4 * 4);
+
+        let mut lexer = Lexer::new("0x100");
+        assert_eq!(
+            parse_data(
+                Bl::Byte,
+                "".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::ImmediateOutOfRange(
+                0x100,
+                -0x80..=0xff,
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 5
+                }
+            ))
+        );
+
+        let mut lexer = Lexer::new("12");
+        assert_eq!(
+            parse_data(
+                Bl::Ascii,
+                "".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::Incomplete(Span {
+                file: 0,
+                row: 0,
+                col: 0,
+                len: 2
+            }))
+        );
+    }
+
     #[test]
     fn data() {
         let input = "12";
         let mut address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_data(Bl::Word, "constant".to_string(), &mut lexer, &mut address).unwrap(),
-            DataNode {
+            parse_data(
+                Bl::Word,
+                "constant".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
+            vec![DataNode {
                 identifier: "constant".to_string(),
+                label_span: Span::default(),
                 address: 0,
                 block: Bl::Word,
                 data: 12,
+                data_expr: None,
+                payload: vec![],
                 num: 1,
-            }
+                span: Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 2
+                },
+            }]
         );
 
         let input = "12";
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_data(Bl::Space, "constant".to_string(), &mut lexer, &mut address).unwrap(),
-            DataNode {
+            parse_data(
+                Bl::Space,
+                "constant".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
+            vec![DataNode {
                 identifier: "constant".to_string(),
+                label_span: Span::default(),
                 address: 4,
                 block: Bl::Space,
                 data: 0,
+                data_expr: None,
+                payload: vec![],
                 num: 12,
-            }
+                span: Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 2
+                },
+            }]
         );
 
         assert_eq!(address, 4 * 13);
 
         let input = "-12";
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_data(Bl::Space, "constant".to_string(), &mut lexer, &mut address),
-            Err(ParserError::NegativeSpace(Loc { row: 0, col: 0 }))
+            parse_data(
+                Bl::Space,
+                "constant".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::NegativeSpace(Span {
+                file: 0,
+                row: 0,
+                col: 0,
+                len: 3
+            }))
         );
 
         let input = "0xffff0000";
         address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_data(Bl::Addr, "io".to_string(), &mut lexer, &mut address).unwrap(),
-            DataNode {
+            parse_data(
+                Bl::Addr,
+                "io".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
+            vec![DataNode {
                 identifier: "io".to_string(),
+                label_span: Span::default(),
                 address: 0xffff0000,
                 block: Bl::Addr,
                 data: 0,
+                data_expr: None,
+                payload: vec![],
                 num: 1,
-            }
+                span: Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 10
+                },
+            }]
         );
 
         assert_eq!(address, 0);
 
         let input = "-4";
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
+        assert_eq!(
+            parse_data(
+                Bl::Addr,
+                "constant".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::NegativeSpace(Span {
+                file: 0,
+                row: 0,
+                col: 0,
+                len: 2
+            }))
+        );
+    }
+
+    #[test]
+    fn word_range() {
+        let mut address = 0;
+
+        let mut lexer = Lexer::new("0xffffffff");
+        assert!(parse_data(
+            Bl::Word,
+            String::new(),
+            Span::default(),
+            &mut lexer,
+            &Constants::new(),
+            &mut address
+        )
+        .is_ok());
+
+        let mut lexer = Lexer::new("-2147483648");
+        assert!(parse_data(
+            Bl::Word,
+            String::new(),
+            Span::default(),
+            &mut lexer,
+            &Constants::new(),
+            &mut address
+        )
+        .is_ok());
+
+        let mut lexer = Lexer::new("-2147483649");
+        assert_eq!(
+            parse_data(
+                Bl::Word,
+                String::new(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::ImmediateOutOfRange(
+                -2147483649,
+                WORD_RANGE,
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 11
+                }
+            ))
+        );
+
+        let mut lexer = Lexer::new("0x100000000");
+        assert_eq!(
+            parse_data(
+                Bl::Word,
+                String::new(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::ImmediateOutOfRange(
+                0x1_0000_0000,
+                WORD_RANGE,
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 11
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn address_space() {
+        let mut address = 0xffff_fff0;
+
+        let mut lexer = Lexer::new("5");
+        assert_eq!(
+            parse_data(
+                Bl::Space,
+                String::new(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::ImmediateOutOfRange(
+                5,
+                0..=4,
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 1
+                }
+            ))
+        );
+
+        let mut lexer = Lexer::new("3");
+        assert!(parse_data(
+            Bl::Space,
+            String::new(),
+            Span::default(),
+            &mut lexer,
+            &Constants::new(),
+            &mut address
+        )
+        .is_ok());
+        assert_eq!(address, 0xffff_fffc);
+
+        // the last word ends right at the end of the address space
+        let mut lexer = Lexer::new("1");
+        assert!(parse_data(
+            Bl::Word,
+            String::new(),
+            Span::default(),
+            &mut lexer,
+            &Constants::new(),
+            &mut address
+        )
+        .is_ok());
+        assert_eq!(address, 1 << 32);
+
+        for (block, input) in [(Bl::Word, "1"), (Bl::Space, "1"), (Bl::Space, "0")] {
+            let mut lexer = Lexer::new(input);
+            assert_eq!(
+                parse_data(
+                    block,
+                    String::new(),
+                    Span::default(),
+                    &mut lexer,
+                    &Constants::new(),
+                    &mut address
+                ),
+                Err(ParserError::SegmentFull(Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 1
+                }))
+            );
+        }
+
+        let mut address = 0xffff_fff8;
+        let mut lexer = Lexer::new("\"abcdefghi\"");
         assert_eq!(
-            parse_data(Bl::Addr, "constant".to_string(), &mut lexer, &mut address),
-            Err(ParserError::NegativeSpace(Loc { row: 0, col: 0 }))
+            parse_data(
+                Bl::Ascii,
+                String::new(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::AddressOverflow(Span {
+                file: 0,
+                row: 0,
+                col: 0,
+                len: 11
+            }))
+        );
+
+        let mut lexer = Lexer::new("0x100000000");
+        assert_eq!(
+            parse_data(
+                Bl::Addr,
+                String::new(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::ImmediateOutOfRange(
+                0x1_0000_0000,
+                0..=0xffff_ffff,
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 11
+                }
+            ))
+        );
+
+        let mut lexer = Lexer::new("label");
+        assert_eq!(
+            parse_data(
+                Bl::Space,
+                String::new(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            ),
+            Err(ParserError::UndefinedConstant(
+                "label".to_string(),
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 5
+                }
+            ))
         );
     }
 }
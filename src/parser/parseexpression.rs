@@ -0,0 +1,284 @@
+use super::parserhelpers::*;
+use super::*;
+
+/// how deeply operators and parentheses may nest in a single expression
+pub const MAX_EXPRESSION_DEPTH: usize = 256;
+
+/// # Get expression
+///
+/// read an expression, along with the span it covers
+///
+/// binary operators bind as in C, unary operators bind tighter than any of them
+pub fn get_expression(lexer: &mut Lexer<'_>) -> Result<(Span, Expr), ParserError> {
+    parse_binary(lexer, 0, 0)
+}
+
+/// parse operands joined by binary operators of at least the given precedence
+///
+/// depth counts the operators and parentheses the expression is nested in
+fn parse_binary(
+    lexer: &mut Lexer<'_>,
+    precedence: u8,
+    mut depth: usize,
+) -> Result<(Span, Expr), ParserError> {
+    let (span, mut expr) = parse_unary(lexer, depth)?;
+
+    while let Some(Ok((op_span, Token::Operator(op)))) = lexer.peek() {
+        let (op_span, op) = (*op_span, *op);
+        let op_precedence = match op.precedence() {
+            Some(p) if p >= precedence => p,
+            _ => break,
+        };
+        lexer.next();
+
+        // every operator nests the expression built so far one level deeper
+        depth += 1;
+        if depth > MAX_EXPRESSION_DEPTH {
+            return Err(ParserError::NestingTooDeep(op_span));
+        }
+
+        // operators of the same precedence associate to the left
+        let (_, rhs) = parse_binary(lexer, op_precedence + 1, depth)?;
+        expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+    }
+
+    Ok((span.to(lexer.end()), expr))
+}
+
+/// parse a single operand: a value, an identifier,
+/// a unary operator applied to an operand, a relocation operator
+/// or an expression in parentheses
+fn parse_unary(lexer: &mut Lexer<'_>, depth: usize) -> Result<(Span, Expr), ParserError> {
+    let (span, token) = read_token(lexer)?;
+    let nested = matches!(token, Token::Operator(_) | Token::LParen);
+    if nested && depth >= MAX_EXPRESSION_DEPTH {
+        return Err(ParserError::NestingTooDeep(span));
+    }
+
+    match (span, token) {
+        (span, Token::Immediate(x)) => Ok((span, Expr::Value(x))),
+        (span, Token::Identifier(str)) => Ok((span, Expr::Identifier(str))),
+        (span, Token::Operator(op @ (Op::Add | Op::Sub | Op::Not))) => {
+            let (_, operand) = parse_unary(lexer, depth + 1)?;
+            let expr = match (op, operand) {
+                // negative numbers are read as values directly
+                (Op::Sub, Expr::Value(x)) => Expr::Value(x.wrapping_neg()),
+                (Op::Add, operand) => operand,
+                (op, operand) => Expr::Unary(op, Box::new(operand)),
+            };
+            Ok((span.to(lexer.end()), expr))
+        }
+        // relocation operators apply to an expression in parentheses
+        (span, Token::Operator(op @ (Op::Hi | Op::Lo | Op::Hia))) => {
+            sel_token(lexer, Token::LParen)?;
+            let (_, operand) = parse_binary(lexer, 0, depth + 1)?;
+            sel_token(lexer, Token::RParen)?;
+            Ok((span.to(lexer.end()), Expr::Unary(op, Box::new(operand))))
+        }
+        (span, Token::LParen) => {
+            let (_, expr) = parse_binary(lexer, 0, depth + 1)?;
+            sel_token(lexer, Token::RParen)?;
+            Ok((span.to(lexer.end()), expr))
+        }
+        (span, _) => Err(ParserError::Incomplete(span)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(op: Op, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+    }
+
+    #[test]
+    fn expression() {
+        let mut lexer = Lexer::new("(SIZE*4)-1 buffer+8 end - start -5");
+
+        assert_eq!(
+            get_expression(&mut lexer),
+            Ok((
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 10
+                },
+                binary(
+                    Op::Sub,
+                    binary(
+                        Op::Mul,
+                        Expr::Identifier("SIZE".to_string()),
+                        Expr::Value(4)
+                    ),
+                    Expr::Value(1)
+                )
+            ))
+        );
+        assert_eq!(
+            get_expression(&mut lexer).unwrap().1,
+            binary(
+                Op::Add,
+                Expr::Identifier("buffer".to_string()),
+                Expr::Value(8)
+            )
+        );
+        assert_eq!(
+            get_expression(&mut lexer).unwrap().1,
+            binary(
+                Op::Sub,
+                binary(
+                    Op::Sub,
+                    Expr::Identifier("end".to_string()),
+                    Expr::Identifier("start".to_string())
+                ),
+                Expr::Value(5)
+            )
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        let mut lexer = Lexer::new("1 | 2 ^ 3 & 4 << 5 + 6 * -~7");
+
+        let expected = binary(
+            Op::Or,
+            Expr::Value(1),
+            binary(
+                Op::Xor,
+                Expr::Value(2),
+                binary(
+                    Op::And,
+                    Expr::Value(3),
+                    binary(
+                        Op::Shl,
+                        Expr::Value(4),
+                        binary(
+                            Op::Add,
+                            Expr::Value(5),
+                            binary(
+                                Op::Mul,
+                                Expr::Value(6),
+                                Expr::Unary(
+                                    Op::Sub,
+                                    Box::new(Expr::Unary(Op::Not, Box::new(Expr::Value(7)))),
+                                ),
+                            ),
+                        ),
+                    ),
+                ),
+            ),
+        );
+
+        assert_eq!(get_expression(&mut lexer).unwrap().1, expected);
+    }
+
+    #[test]
+    fn malformed() {
+        let mut lexer = Lexer::new("(1 + 2");
+        assert_eq!(
+            get_expression(&mut lexer),
+            Err(ParserError::End(Span {
+                file: 0,
+                row: 0,
+                col: 6,
+                len: 0
+            }))
+        );
+
+        let mut lexer = Lexer::new("1 + , 2");
+        assert_eq!(
+            get_expression(&mut lexer),
+            Err(ParserError::Incomplete(Span {
+                file: 0,
+                row: 0,
+                col: 4,
+                len: 1
+            }))
+        );
+    }
+
+    #[test]
+    fn nesting() {
+        let depth = MAX_EXPRESSION_DEPTH;
+        let input = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        let mut lexer = Lexer::new(&input);
+        assert_eq!(get_expression(&mut lexer).unwrap().1, Expr::Value(1));
+
+        let input = format!("{}1", "-".repeat(depth));
+        let mut lexer = Lexer::new(&input);
+        assert_eq!(get_expression(&mut lexer).unwrap().1, Expr::Value(1));
+
+        let input = format!("{}1{}", "(".repeat(5000), ")".repeat(5000));
+        let mut lexer = Lexer::new(&input);
+        assert_eq!(
+            get_expression(&mut lexer),
+            Err(ParserError::NestingTooDeep(Span {
+                file: 0,
+                row: 0,
+                col: depth as u32,
+                len: 1
+            }))
+        );
+
+        let input = format!("{}1", "-~".repeat(2500));
+        let mut lexer = Lexer::new(&input);
+        assert_eq!(
+            get_expression(&mut lexer),
+            Err(ParserError::NestingTooDeep(Span {
+                file: 0,
+                row: 0,
+                col: depth as u32,
+                len: 1
+            }))
+        );
+
+        let input = format!("1{}", "+1".repeat(5000));
+        let mut lexer = Lexer::new(&input);
+        assert_eq!(
+            get_expression(&mut lexer),
+            Err(ParserError::NestingTooDeep(Span {
+                file: 0,
+                row: 0,
+                col: 2 * depth as u32 + 1,
+                len: 1
+            }))
+        );
+    }
+
+    #[test]
+    fn relocation() {
+        let mut lexer = Lexer::new("%hia(buffer + 4) %lo buffer");
+
+        assert_eq!(
+            get_expression(&mut lexer),
+            Ok((
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 16
+                },
+                Expr::Unary(
+                    Op::Hia,
+                    Box::new(binary(
+                        Op::Add,
+                        Expr::Identifier("buffer".to_string()),
+                        Expr::Value(4)
+                    ))
+                )
+            ))
+        );
+
+        assert_eq!(
+            get_expression(&mut lexer),
+            Err(ParserError::Incomplete(Span {
+                file: 0,
+                row: 0,
+                col: 21,
+                len: 6
+            }))
+        );
+    }
+}
@@ -4,11 +4,15 @@ use super::*;
 /// given an instruction identifier, parse the rest of the instruction and return the node
 ///
 /// returns a vector of nodes, since some instructions are in reality composed of several others
+///
+/// all nodes span from the instruction identifier to the end of the statement
 pub fn parse_instruction(
     instruction: Instr,
     mut identifier: String,
+    span: Span,
     lexer: &mut Lexer<'_>,
-    address: &mut u32,
+    constants: &Constants,
+    address: &mut u64,
 ) -> Result<Vec<InstructionNode>, ParserError> {
     let mut return_vector: Vec<InstructionNode> = Vec::new(); // buffer for instruction nodes
 
@@ -17,89 +21,98 @@ pub fn parse_instruction(
     let mut ra = 0;
     let mut rb = 0;
     let mut imm = 0;
-    let mut imm_identifier = String::new();
+    let mut imm_expr = None;
 
     match op {
         // instructions in the form "instr $rd, $ra, $rb"
         Instr::And | Instr::Or | Instr::Xor | Instr::Add | Instr::Sub | Instr::Cmp => {
-            rd = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            ra = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            rb = get_register(lexer.next())?;
+            rd = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            ra = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            rb = get_register(lexer)?;
         }
         // instructions in the form "instr $rd, $ra"
         Instr::Not | Instr::J => {
-            rd = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            ra = get_register(lexer.next())?;
+            rd = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            ra = get_register(lexer)?;
         }
         // instructions in the form "instr $rd, $ra, immediate"
         Instr::Sl | Instr::Sr | Instr::Addi => {
-            rd = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            ra = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            imm = get_immediate(lexer.next())?;
+            rd = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            ra = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            (imm_expr, imm) = get_expression_or_imm(lexer, constants, op.imm_range())?;
         }
         // instructions in the form "instr $ra, $rb, immediate/identifier"
         Instr::Beq | Instr::Bne => {
-            ra = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            rb = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            (imm_identifier, imm) = get_identifier_or_imm(lexer.next())?;
+            ra = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            rb = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            (imm_expr, imm) = get_expression_or_imm(lexer, constants, op.imm_range())?;
         }
         // instructions in the form "instr $rd, immediate"
         Instr::Lui => {
-            rd = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            imm = get_immediate(lexer.next())?;
+            rd = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            (imm_expr, imm) = get_expression_or_imm(lexer, constants, op.imm_range())?;
         }
         // instructions in the form "instr $rd, $rb"
         Instr::Lw => {
-            rd = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            rb = get_register(lexer.next())?;
+            rd = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            rb = get_register(lexer)?;
         }
         // instructions in the form "instr $ra, $rb"
         Instr::Sw => {
-            ra = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            rb = get_register(lexer.next())?;
+            ra = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            rb = get_register(lexer)?;
         }
 
         Instr::La => {
-            // la is a pseudoinstruction that is composed of 5 others
-            rd = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            (imm_identifier, imm) = get_identifier_or_imm(lexer.next())?;
+            // la is a pseudoinstruction that is composed of 5 others:
+            // %hi of the address is loaded into $at, %lo into rd,
+            // whose sign extension is cleared by shifting before the two are added
+            rd = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            let expr;
+            (expr, imm) = get_expression_or_imm(lexer, constants, op.imm_range())?;
 
             return_vector.push(InstructionNode {
                 op: Instr::Lui,
                 rd: 1,
                 ra: 0,
                 rb: 0,
-                imm: imm >> 16,
+                imm: Op::Hi.unary(imm),
                 identifier,
-                imm_identifier: imm_identifier.clone(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: expr.clone().map(|e| Expr::Unary(Op::Hi, Box::new(e))),
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             return_vector.push(InstructionNode {
                 op: Instr::Addi,
                 rd,
                 ra: 0,
                 rb: 0,
-                imm: imm & 0xffff,
+                imm: Op::Lo.unary(imm),
                 identifier: String::new(),
-                imm_identifier: imm_identifier.clone(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: expr.clone().map(|e| Expr::Unary(Op::Lo, Box::new(e))),
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             return_vector.push(InstructionNode {
                 op: Instr::Sl,
@@ -108,11 +121,14 @@ pub fn parse_instruction(
                 rb: 0,
                 imm: 16,
                 identifier: String::new(),
-                imm_identifier: imm_identifier.clone(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: None,
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             return_vector.push(InstructionNode {
                 op: Instr::Sr,
@@ -121,11 +137,14 @@ pub fn parse_instruction(
                 rb: 0,
                 imm: 16,
                 identifier: String::new(),
-                imm_identifier: imm_identifier.clone(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: None,
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             op = Instr::Add;
             ra = 1;
@@ -136,35 +155,42 @@ pub fn parse_instruction(
 
         Instr::Ja => {
             // ja consists of six instructions
-            rd = get_register(lexer.next())?;
-            sel_token(lexer.next(), Token::Comma)?;
-            (imm_identifier, imm) = get_identifier_or_imm(lexer.next())?;
+            rd = get_register(lexer)?;
+            sel_token(lexer, Token::Comma)?;
+            let expr;
+            (expr, imm) = get_expression_or_imm(lexer, constants, op.imm_range())?;
 
             return_vector.push(InstructionNode {
                 op: Instr::Lui,
                 rd: 1,
                 ra: 0,
                 rb: 0,
-                imm: imm >> 16,
+                imm: Op::Hi.unary(imm),
                 identifier,
-                imm_identifier: imm_identifier.clone(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: expr.clone().map(|e| Expr::Unary(Op::Hi, Box::new(e))),
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             return_vector.push(InstructionNode {
                 op: Instr::Addi,
                 rd,
                 ra: 0,
                 rb: 0,
-                imm: imm & 0xffff,
+                imm: Op::Lo.unary(imm),
                 identifier: String::new(),
-                imm_identifier: imm_identifier.clone(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: expr.clone().map(|e| Expr::Unary(Op::Lo, Box::new(e))),
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             return_vector.push(InstructionNode {
                 op: Instr::Sl,
@@ -173,11 +199,14 @@ pub fn parse_instruction(
                 rb: 0,
                 imm: 16,
                 identifier: String::new(),
-                imm_identifier: imm_identifier.clone(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: None,
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             return_vector.push(InstructionNode {
                 op: Instr::Sr,
@@ -186,11 +215,14 @@ pub fn parse_instruction(
                 rb: 0,
                 imm: 16,
                 identifier: String::new(),
-                imm_identifier: imm_identifier.clone(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: None,
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             return_vector.push(InstructionNode {
                 op: Instr::Add,
@@ -199,17 +231,19 @@ pub fn parse_instruction(
                 rb: rd,
                 imm: 0,
                 identifier: String::new(),
-                imm_identifier: imm_identifier.clone(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: None,
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             op = Instr::J;
             ra = 1;
             identifier = String::new();
             imm = 0;
-            imm_identifier = String::new();
         }
 
         Instr::Push => {
@@ -221,20 +255,23 @@ pub fn parse_instruction(
                 rb: 0,
                 imm: -4,
                 identifier,
-                imm_identifier: String::new(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: None,
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             op = Instr::Sw;
-            ra = get_register(lexer.next())?;
+            ra = get_register(lexer)?;
             rb = 15;
             identifier = String::new();
         }
         Instr::Pop => {
             // pop consists of two instructions
-            rd = get_register(lexer.next())?;
+            rd = get_register(lexer)?;
 
             return_vector.push(InstructionNode {
                 op: Instr::Lw,
@@ -243,11 +280,14 @@ pub fn parse_instruction(
                 rb: 15,
                 imm: 0,
                 identifier,
-                imm_identifier: String::new(),
-                address: *address,
+                label_span: Span::default(),
+                imm_expr: None,
+                address: *address as u32,
+                placement: None,
+                span,
             });
 
-            *address += 4;
+            next_address(address, span, lexer)?;
 
             op = Instr::Addi;
             rd = 15;
@@ -264,14 +304,32 @@ pub fn parse_instruction(
         rb,
         imm,
         identifier,
-        imm_identifier,
-        address: *address,
+        label_span: Span::default(),
+        imm_expr,
+        address: *address as u32,
+        placement: None,
+        span,
     });
-    *address += 4; // each instruction lies 4 bytes after the next
+    next_address(address, span, lexer)?; // each instruction lies 4 bytes after the next
+
+    // every node covers the whole statement it was parsed from
+    let span = span.to(lexer.end());
+    for node in &mut return_vector {
+        node.span = span;
+    }
 
     Ok(return_vector)
 }
 
+/// move address past an instruction, which has to end within the address space
+fn next_address(address: &mut u64, span: Span, lexer: &Lexer<'_>) -> Result<(), ParserError> {
+    *address += 4;
+    if *address > ADDRESS_SPACE_END {
+        return Err(ParserError::AddressOverflow(span.to(lexer.end())));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -280,10 +338,18 @@ mod tests {
     fn instruction() {
         let input = "$1, $2, $3 ";
         let mut address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_instruction(Instr::Add, "loop".to_string(), &mut lexer, &mut address).unwrap(),
+            parse_instruction(
+                Instr::Add,
+                "loop".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
             vec![InstructionNode {
                 op: Instr::Add,
                 rd: 1,
@@ -291,17 +357,33 @@ mod tests {
                 rb: 3,
                 imm: 0,
                 identifier: "loop".to_string(),
-                imm_identifier: String::new(),
+                label_span: Span::default(),
+                imm_expr: None,
                 address: 0,
+                placement: None,
+                span: Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 10
+                },
             }]
         );
 
         let input = "$1, $2, 0xf";
         let mut address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_instruction(Instr::Addi, "loop".to_string(), &mut lexer, &mut address).unwrap(),
+            parse_instruction(
+                Instr::Addi,
+                "loop".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
             vec![InstructionNode {
                 op: Instr::Addi,
                 rd: 1,
@@ -309,17 +391,33 @@ mod tests {
                 rb: 0,
                 imm: 15,
                 identifier: "loop".to_string(),
-                imm_identifier: String::new(),
+                label_span: Span::default(),
+                imm_expr: None,
                 address: 0,
+                placement: None,
+                span: Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 11
+                },
             }]
         );
 
         let input = "$1, $2, loop1";
         let mut address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_instruction(Instr::Beq, "loop".to_string(), &mut lexer, &mut address).unwrap(),
+            parse_instruction(
+                Instr::Beq,
+                "loop".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
             vec![InstructionNode {
                 op: Instr::Beq,
                 rd: 0,
@@ -327,17 +425,33 @@ mod tests {
                 rb: 2,
                 imm: 0,
                 identifier: "loop".to_string(),
-                imm_identifier: "loop1".to_string(),
+                label_span: Span::default(),
+                imm_expr: Some(Expr::Identifier("loop1".to_string())),
                 address: 0,
+                placement: None,
+                span: Span {
+                    file: 0,
+                    row: 0,
+                    col: 0,
+                    len: 13
+                },
             }]
         );
 
         let input = "$8, loop1";
         let mut address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_instruction(Instr::La, "loop".to_string(), &mut lexer, &mut address).unwrap(),
+            parse_instruction(
+                Instr::La,
+                "loop".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
             vec![
                 InstructionNode {
                     op: Instr::Lui,
@@ -346,8 +460,19 @@ mod tests {
                     rb: 0,
                     imm: 0,
                     identifier: "loop".to_string(),
-                    imm_identifier: "loop1".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(Expr::Unary(
+                        Op::Hi,
+                        Box::new(Expr::Identifier("loop1".to_string()))
+                    )),
                     address: 0,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 },
                 InstructionNode {
                     op: Instr::Addi,
@@ -356,8 +481,19 @@ mod tests {
                     rb: 0,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "loop1".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(Expr::Unary(
+                        Op::Lo,
+                        Box::new(Expr::Identifier("loop1".to_string()))
+                    )),
                     address: 4,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 },
                 InstructionNode {
                     op: Instr::Sl,
@@ -366,8 +502,16 @@ mod tests {
                     rb: 0,
                     imm: 16,
                     identifier: "".to_string(),
-                    imm_identifier: "loop1".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 8,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 },
                 InstructionNode {
                     op: Instr::Sr,
@@ -376,8 +520,16 @@ mod tests {
                     rb: 0,
                     imm: 16,
                     identifier: "".to_string(),
-                    imm_identifier: "loop1".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 12,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 },
                 InstructionNode {
                     op: Instr::Add,
@@ -386,18 +538,34 @@ mod tests {
                     rb: 8,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "loop1".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 16,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 }
             ]
         );
 
         let input = "$8, 0x12345678";
         let mut address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_instruction(Instr::La, "loop".to_string(), &mut lexer, &mut address).unwrap(),
+            parse_instruction(
+                Instr::La,
+                "loop".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
             vec![
                 InstructionNode {
                     op: Instr::Lui,
@@ -406,8 +574,16 @@ mod tests {
                     rb: 0,
                     imm: 0x1234,
                     identifier: "loop".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 0,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 },
                 InstructionNode {
                     op: Instr::Addi,
@@ -416,8 +592,16 @@ mod tests {
                     rb: 0,
                     imm: 0x5678,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 4,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 },
                 InstructionNode {
                     op: Instr::Sl,
@@ -426,8 +610,16 @@ mod tests {
                     rb: 0,
                     imm: 16,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 8,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 },
                 InstructionNode {
                     op: Instr::Sr,
@@ -436,8 +628,16 @@ mod tests {
                     rb: 0,
                     imm: 16,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 12,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 },
                 InstructionNode {
                     op: Instr::Add,
@@ -446,18 +646,34 @@ mod tests {
                     rb: 8,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 16,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 }
             ]
         );
 
         let input = "$8, loop1";
         let mut address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_instruction(Instr::Ja, "loop".to_string(), &mut lexer, &mut address).unwrap(),
+            parse_instruction(
+                Instr::Ja,
+                "loop".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
             vec![
                 InstructionNode {
                     op: Instr::Lui,
@@ -466,8 +682,19 @@ mod tests {
                     rb: 0,
                     imm: 0,
                     identifier: "loop".to_string(),
-                    imm_identifier: "loop1".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(Expr::Unary(
+                        Op::Hi,
+                        Box::new(Expr::Identifier("loop1".to_string()))
+                    )),
                     address: 0,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 },
                 InstructionNode {
                     op: Instr::Addi,
@@ -476,8 +703,19 @@ mod tests {
                     rb: 0,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "loop1".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(Expr::Unary(
+                        Op::Lo,
+                        Box::new(Expr::Identifier("loop1".to_string()))
+                    )),
                     address: 4,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 },
                 InstructionNode {
                     op: Instr::Sl,
@@ -486,8 +724,16 @@ mod tests {
                     rb: 0,
                     imm: 16,
                     identifier: "".to_string(),
-                    imm_identifier: "loop1".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 8,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 },
                 InstructionNode {
                     op: Instr::Sr,
@@ -496,8 +742,16 @@ mod tests {
                     rb: 0,
                     imm: 16,
                     identifier: "".to_string(),
-                    imm_identifier: "loop1".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 12,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 },
                 InstructionNode {
                     op: Instr::Add,
@@ -506,8 +760,16 @@ mod tests {
                     rb: 8,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "loop1".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 16,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 },
                 InstructionNode {
                     op: Instr::J,
@@ -516,18 +778,34 @@ mod tests {
                     rb: 0,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 20,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 9
+                    },
                 }
             ]
         );
 
         let input = "$8, 0x12345678";
         let mut address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_instruction(Instr::Ja, "loop".to_string(), &mut lexer, &mut address).unwrap(),
+            parse_instruction(
+                Instr::Ja,
+                "loop".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
             vec![
                 InstructionNode {
                     op: Instr::Lui,
@@ -536,8 +814,16 @@ mod tests {
                     rb: 0,
                     imm: 0x1234,
                     identifier: "loop".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 0,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 },
                 InstructionNode {
                     op: Instr::Addi,
@@ -546,8 +832,16 @@ mod tests {
                     rb: 0,
                     imm: 0x5678,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 4,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 },
                 InstructionNode {
                     op: Instr::Sl,
@@ -556,8 +850,16 @@ mod tests {
                     rb: 0,
                     imm: 16,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 8,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 },
                 InstructionNode {
                     op: Instr::Sr,
@@ -566,8 +868,16 @@ mod tests {
                     rb: 0,
                     imm: 16,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 12,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 },
                 InstructionNode {
                     op: Instr::Add,
@@ -576,8 +886,16 @@ mod tests {
                     rb: 8,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 16,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 },
                 InstructionNode {
                     op: Instr::J,
@@ -586,18 +904,34 @@ mod tests {
                     rb: 0,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 20,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 14
+                    },
                 }
             ]
         );
 
         let input = "$t0";
         let mut address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_instruction(Instr::Push, "loop".to_string(), &mut lexer, &mut address).unwrap(),
+            parse_instruction(
+                Instr::Push,
+                "loop".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
             vec![
                 InstructionNode {
                     op: Instr::Addi,
@@ -606,8 +940,16 @@ mod tests {
                     rb: 0,
                     imm: -4,
                     identifier: "loop".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 0,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 3
+                    },
                 },
                 InstructionNode {
                     op: Instr::Sw,
@@ -616,18 +958,34 @@ mod tests {
                     rb: 15,
                     imm: 0,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 4,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 3
+                    },
                 },
             ]
         );
 
         let input = "$t0";
         let mut address = 0;
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            parse_instruction(Instr::Pop, "loop".to_string(), &mut lexer, &mut address).unwrap(),
+            parse_instruction(
+                Instr::Pop,
+                "loop".to_string(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address
+            )
+            .unwrap(),
             vec![
                 InstructionNode {
                     op: Instr::Lw,
@@ -636,8 +994,16 @@ mod tests {
                     rb: 15,
                     imm: 0,
                     identifier: "loop".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 0,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 3
+                    },
                 },
                 InstructionNode {
                     op: Instr::Addi,
@@ -646,10 +1012,70 @@ mod tests {
                     rb: 0,
                     imm: 4,
                     identifier: "".to_string(),
-                    imm_identifier: "".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: None,
                     address: 4,
+                    placement: None,
+                    span: Span {
+                        file: 0,
+                        row: 0,
+                        col: 0,
+                        len: 3
+                    },
                 },
             ]
         );
     }
+
+    #[test]
+    fn immediate_range() {
+        let cases = [
+            (Instr::Addi, "$1, $2, 32767", None),
+            (Instr::Addi, "$1, $2, -32768", None),
+            (Instr::Addi, "$1, $2, 100000", Some((100000, 8, 6))),
+            (Instr::Addi, "$1, $2, -32769", Some((-32769, 8, 6))),
+            (Instr::Lui, "$1, 0xffff", None),
+            (Instr::Lui, "$1, -1", Some((-1, 4, 2))),
+            (Instr::Lui, "$1, 0x10000", Some((0x10000, 4, 7))),
+            (Instr::Sl, "$1, $2, 31", None),
+            (Instr::Sr, "$1, $2, 32", Some((32, 8, 2))),
+            (Instr::Beq, "$1, $2, -8", None),
+            (Instr::Bne, "$1, $2, 0x8000", Some((0x8000, 8, 6))),
+            (Instr::La, "$1, 0xffffffff", None),
+            (Instr::Ja, "$1, 0x100000000", Some((0x1_0000_0000, 4, 11))),
+        ];
+
+        for (op, input, error) in cases {
+            let mut address = 0;
+            let mut lexer = Lexer::new(input);
+
+            let range = op.imm_range();
+            let result = parse_instruction(
+                op,
+                String::new(),
+                Span::default(),
+                &mut lexer,
+                &Constants::new(),
+                &mut address,
+            );
+
+            match error {
+                None => assert!(result.is_ok(), "{input}"),
+                Some((imm, col, len)) => assert_eq!(
+                    result,
+                    Err(ParserError::ImmediateOutOfRange(
+                        imm,
+                        range,
+                        Span {
+                            file: 0,
+                            row: 0,
+                            col,
+                            len
+                        }
+                    )),
+                    "{input}"
+                ),
+            }
+        }
+    }
 }
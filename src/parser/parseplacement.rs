@@ -0,0 +1,144 @@
+use super::parserhelpers::*;
+use super::*;
+
+/// given the block identifier of a placement directive, parse the rest of it
+/// and move the address of the current segment, returning how it was moved
+///
+/// form: .align immediate
+/// pads to the next multiple of 2^immediate bytes
+///
+/// form: .org immediate
+/// places what follows at the word aligned address immediate,
+/// which has to lie within range
+pub fn parse_placement(
+    block: Bl,
+    lexer: &mut Lexer<'_>,
+    constants: &Constants,
+    address: &mut u64,
+    range: RangeInclusive<i64>,
+) -> Result<Placement, ParserError> {
+    let placement = match block {
+        Bl::Align => {
+            let (span, exponent) = get_value(lexer, constants)?;
+            if !(0..=31).contains(&exponent) {
+                return Err(ParserError::ImmediateOutOfRange(exponent, 0..=31, span));
+            }
+
+            let boundary = 1u64 << exponent;
+            let aligned = address.div_ceil(boundary) * boundary;
+            if !range.contains(&(aligned as i64)) {
+                return Err(ParserError::AddressOverflow(span));
+            }
+            *address = aligned;
+            Placement::Align(boundary as u32)
+        }
+        Bl::Org => {
+            let (span, target) = get_value(lexer, constants)?;
+            if !range.contains(&target) {
+                return Err(ParserError::ImmediateOutOfRange(target, range, span));
+            }
+            if target % 4 != 0 {
+                return Err(ParserError::UnalignedAddress(span));
+            }
+            *address = target as u64;
+            Placement::Org(target as u32)
+        }
+        _ => {
+            let (span, _) = read_token(lexer)?;
+            return Err(ParserError::Incomplete(span));
+        }
+    };
+
+    Ok(placement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placement() {
+        let range = 0..=0xffff;
+        let mut address = 0x14;
+        let mut lexer = Lexer::new("4 2 0x40 0x42 0x10000 16");
+
+        assert_eq!(
+            parse_placement(
+                Bl::Align,
+                &mut lexer,
+                &Constants::new(),
+                &mut address,
+                range.clone(),
+            ),
+            Ok(Placement::Align(16))
+        );
+        assert_eq!(address, 0x20);
+
+        assert_eq!(
+            parse_placement(
+                Bl::Align,
+                &mut lexer,
+                &Constants::new(),
+                &mut address,
+                range.clone(),
+            ),
+            Ok(Placement::Align(4))
+        );
+        assert_eq!(address, 0x20);
+
+        assert_eq!(
+            parse_placement(
+                Bl::Org,
+                &mut lexer,
+                &Constants::new(),
+                &mut address,
+                range.clone(),
+            ),
+            Ok(Placement::Org(0x40))
+        );
+        assert_eq!(address, 0x40);
+
+        let span = |col, len| Span {
+            file: 0,
+            row: 0,
+            col,
+            len,
+        };
+
+        assert_eq!(
+            parse_placement(
+                Bl::Org,
+                &mut lexer,
+                &Constants::new(),
+                &mut address,
+                range.clone()
+            ),
+            Err(ParserError::UnalignedAddress(span(9, 4)))
+        );
+        assert_eq!(
+            parse_placement(
+                Bl::Org,
+                &mut lexer,
+                &Constants::new(),
+                &mut address,
+                range.clone()
+            ),
+            Err(ParserError::ImmediateOutOfRange(
+                0x10000,
+                range.clone(),
+                span(14, 7)
+            ))
+        );
+        assert_eq!(
+            parse_placement(
+                Bl::Align,
+                &mut lexer,
+                &Constants::new(),
+                &mut address,
+                range
+            ),
+            Err(ParserError::AddressOverflow(span(22, 2)))
+        );
+        assert_eq!(address, 0x40);
+    }
+}
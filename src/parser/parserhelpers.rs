@@ -1,182 +1,445 @@
-pub type LexerResult = Result<(lexgen_util::Loc, Token, lexgen_util::Loc), LexerError<Infallible>>;
-pub type Lexer<'a> = mylexer::Lexer_<'a, std::str::Chars<'a>, ()>;
+pub type LexerResult =
+    Result<(lexgen_util::Loc, Token, lexgen_util::Loc), LexerError<mylexer::LiteralError>>;
 
 use super::mylexer;
 use super::*;
+use std::ops::RangeInclusive;
+
+/// # Lexer
+///
+/// wraps the generated lexer, reading the source files one after another
+/// and marking each token with its span.
+///
+/// The parser can look one token ahead
+/// and step back over the last token read when recovering from an error
+pub struct Lexer<'a> {
+    inputs: Vec<&'a str>,
+    file: usize,
+    lexer: mylexer::Lexer_<'a, std::str::Chars<'a>, ()>,
+    pending: Vec<ParserResult>,
+    last: Option<ParserResult>,
+    end: Span,
+}
+
+impl<'a> Lexer<'a> {
+    /// lexer for a single input, reported as file 0
+    #[cfg(test)]
+    pub fn new(input: &'a str) -> Self {
+        Lexer::from_inputs(vec![input])
+    }
+
+    /// lexer reading all source files in order, as if they were concatenated
+    pub fn from_sources(sources: &'a [SourceFile]) -> Self {
+        Lexer::from_inputs(sources.iter().map(|f| &f.contents[..]).collect())
+    }
+
+    fn from_inputs(inputs: Vec<&'a str>) -> Self {
+        Lexer {
+            lexer: mylexer::Lexer::new(inputs.first().unwrap_or(&"")),
+            inputs,
+            file: 0,
+            pending: Vec::new(),
+            last: None,
+            end: Span::default(),
+        }
+    }
+
+    /// read the next token from the underlying lexer, moving on to the next file when needed
+    fn lex(&mut self) -> Option<ParserResult> {
+        loop {
+            if let Some(ret) = self.lexer.next() {
+                return Some(parse_token(ret, self.file));
+            }
+
+            self.file += 1;
+            self.lexer = mylexer::Lexer::new(self.inputs.get(self.file)?);
+        }
+    }
+
+    /// look at the next token without consuming it
+    pub fn peek(&mut self) -> Option<&ParserResult> {
+        if self.pending.is_empty() {
+            let next = self.lex()?;
+            self.pending.push(next);
+        }
+        self.pending.last()
+    }
+
+    /// the token most recently returned by next()
+    pub fn last_token(&self) -> Option<&ParserResult> {
+        self.last.as_ref()
+    }
+
+    /// put the last token back, so that next() returns it again
+    pub fn step_back(&mut self) {
+        if let Some(last) = self.last.take() {
+            self.pending.push(last);
+        }
+    }
+
+    /// zero length span right after the last token read
+    pub fn end(&self) -> Span {
+        self.end
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = ParserResult;
+
+    fn next(&mut self) -> Option<ParserResult> {
+        let next = match self.pending.pop() {
+            Some(next) => Some(next),
+            None => self.lex(),
+        };
+
+        if let Some(
+            Ok((span, _))
+            | Err(ParserError::InvalidToken(span) | ParserError::LiteralOverflow(span)),
+        ) = next
+        {
+            self.end = Span {
+                col: span.col + span.len,
+                len: 0,
+                ..span
+            };
+        }
+
+        self.last.clone_from(&next);
+        next
+    }
+}
 
 /// # Parse single token
 ///
 /// converts lexer error types to parser errors
 ///
-/// converts lexer output types to parser input types
-pub fn parse_token(ret: LexerResult) -> ParserResult {
+/// converts lexer output types to parser input types, marking them with the file they are from
+pub fn parse_token(ret: LexerResult, file: usize) -> ParserResult {
     match ret {
-        Ok((l, t, _)) => Ok((
-            Loc {
+        Ok((l, t, e)) => Ok((
+            Span {
+                file,
                 row: l.line,
                 col: l.col,
+                len: if e.line == l.line { e.col - l.col } else { 1 },
             },
             t,
         )),
-        Err(LexerError {
-            location: l,
-            kind: _,
-        }) => Err(ParserError::InvalidToken(Loc {
-            row: l.line,
-            col: l.col,
-        })),
+        Err(LexerError { location: l, kind }) => {
+            let span = Span {
+                file,
+                row: l.line,
+                col: l.col,
+                len: 1,
+            };
+
+            // literal errors are located at the end of the literal
+            let literal = |len: u32| Span {
+                col: l.col.saturating_sub(len),
+                len,
+                ..span
+            };
+
+            match kind {
+                LexerErrorKind::Custom(mylexer::LiteralError::Overflow(len)) => {
+                    Err(ParserError::LiteralOverflow(literal(len)))
+                }
+                LexerErrorKind::Custom(mylexer::LiteralError::Invalid(len)) => {
+                    Err(ParserError::InvalidToken(literal(len)))
+                }
+                LexerErrorKind::InvalidToken => Err(ParserError::InvalidToken(span)),
+            }
+        }
     }
 }
 
 /// # Read single token
 ///
-/// wraps parse_token
-///
-/// returns ParserError::IncompleteInstruction(Loc) if EOF is encountered
-pub fn read_token(ret: Option<LexerResult>) -> ParserResult {
-    let ret = match ret {
+/// returns ParserError::End if EOF is encountered
+pub fn read_token(lexer: &mut Lexer<'_>) -> ParserResult {
+    match lexer.next() {
         Some(x) => x,
-        None => return Err(ParserError::End),
-    };
-
-    parse_token(ret)
+        None => Err(ParserError::End(lexer.end())),
+    }
 }
 
 /// # Select token
 ///
-/// returns ParseError::IncompleteInstruction(Loc) if the next token is not the selected token
-pub fn sel_token(ret: Option<LexerResult>, selection: Token) -> ParserResult {
-    match read_token(ret)? {
-        (loc, token) if token == selection => Ok((loc, token)),
-        (loc, _) => Err(ParserError::Incomplete(loc)),
+/// returns ParseError::Incomplete(Span) if the next token is not the selected token
+pub fn sel_token(lexer: &mut Lexer<'_>, selection: Token) -> ParserResult {
+    match read_token(lexer)? {
+        (span, token) if token == selection => Ok((span, token)),
+        (span, _) => Err(ParserError::Incomplete(span)),
     }
 }
 
 /// # Get register
 ///
-/// uses sel_token to read a register number. Forwards errors
-pub fn get_register(ret: Option<LexerResult>) -> Result<u8, ParserError> {
-    match read_token(ret)? {
+/// uses read_token to read a register number. Forwards errors
+pub fn get_register(lexer: &mut Lexer<'_>) -> Result<u8, ParserError> {
+    match read_token(lexer)? {
         (_, Token::Register(x)) => Ok(x),
-        (l, _) => Err(ParserError::Incomplete(l)),
+        (span, _) => Err(ParserError::Incomplete(span)),
+    }
+}
+
+/// # Get value
+///
+/// uses get_expression to read an expression made up of immediates and constants defined earlier,
+/// returns its value along with its span. Forwards errors
+pub fn get_value(lexer: &mut Lexer<'_>, constants: &Constants) -> Result<(Span, i64), ParserError> {
+    let (span, expr) = get_expression(lexer)?;
+
+    match expr.evaluate(&|id| constants.get(id).map(|c| c.value)) {
+        Ok(x) => Ok((span, x)),
+        Err(ExprError::UnknownIdentifier(id)) => Err(ParserError::UndefinedConstant(id, span)),
+        Err(e) => Err(ParserError::InvalidExpression(e, span)),
     }
 }
 
 /// # Get immediate
 ///
-/// uses sel_token to read an immediate. Forwards errors
-pub fn get_immediate(ret: Option<LexerResult>) -> Result<i64, ParserError> {
-    match read_token(ret)? {
-        (_, Token::Immediate(x)) => Ok(x),
-        (l, _) => Err(ParserError::Incomplete(l)),
+/// uses get_value to read an immediate, which has to lie within range. Forwards errors
+pub fn get_immediate(
+    lexer: &mut Lexer<'_>,
+    constants: &Constants,
+    range: RangeInclusive<i64>,
+) -> Result<i64, ParserError> {
+    match get_value(lexer, constants)? {
+        (_, x) if range.contains(&x) => Ok(x),
+        (span, x) => Err(ParserError::ImmediateOutOfRange(x, range, span)),
     }
 }
 
-/// # Get identifier or immediate
+/// # Get expression or immediate
+///
+/// uses get_expression to read an expression.
+/// Constants defined earlier are replaced with their value.
 ///
-/// uses sel_token to read an immediate. Forwards errors
-pub fn get_identifier_or_imm(ret: Option<LexerResult>) -> Result<(String, i64), ParserError> {
-    match read_token(ret)? {
-        (_, Token::Identifier(str)) => Ok((str, 0)),
-        (_, Token::Immediate(x)) => Ok((String::new(), x)),
-        (loc, _) => Err(ParserError::Incomplete(loc)),
+/// If the expression still uses identifiers, it is returned for the linker to resolve,
+/// otherwise it is evaluated to an immediate, which has to lie within range. Forwards errors
+pub fn get_expression_or_imm(
+    lexer: &mut Lexer<'_>,
+    constants: &Constants,
+    range: RangeInclusive<i64>,
+) -> Result<(Option<Expr>, i64), ParserError> {
+    let (span, expr) = get_expression(lexer)?;
+    let expr = expr.substitute(&|id| constants.get(id).map(|c| c.value));
+
+    if !expr.identifiers().is_empty() {
+        return Ok((Some(expr), 0));
+    }
+
+    match expr.evaluate(&|_| None) {
+        Ok(x) if range.contains(&x) => Ok((None, x)),
+        Ok(x) => Err(ParserError::ImmediateOutOfRange(x, range, span)),
+        Err(e) => Err(ParserError::InvalidExpression(e, span)),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::mylexer;
     use super::*;
 
     #[test]
     fn reading() {
         let input = ", : add $zero loop_start 1600 .word";
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
-        assert_eq!(read_token(lexer.next()).unwrap().1, Token::Comma);
-        assert_eq!(read_token(lexer.next()).unwrap().1, Token::Colon);
+        assert_eq!(read_token(&mut lexer).unwrap().1, Token::Comma);
+        assert_eq!(read_token(&mut lexer).unwrap().1, Token::Colon);
         assert_eq!(
-            read_token(lexer.next()).unwrap().1,
+            read_token(&mut lexer).unwrap().1,
             Token::Instruction(Instr::Add)
         );
-        assert_eq!(read_token(lexer.next()).unwrap().1, Token::Register(0));
+        assert_eq!(read_token(&mut lexer).unwrap().1, Token::Register(0));
         assert_eq!(
-            read_token(lexer.next()).unwrap().1,
+            read_token(&mut lexer).unwrap().1,
             Token::Identifier("loop_start".to_string())
         );
-        assert_eq!(read_token(lexer.next()).unwrap().1, Token::Immediate(1600));
-        assert_eq!(read_token(lexer.next()).unwrap().1, Token::Block(Bl::Word));
-        assert_eq!(read_token(lexer.next()), Err(ParserError::End));
+        assert_eq!(read_token(&mut lexer).unwrap().1, Token::Immediate(1600));
+        assert_eq!(read_token(&mut lexer).unwrap().1, Token::Block(Bl::Word));
+        assert_eq!(
+            read_token(&mut lexer),
+            Err(ParserError::End(Span {
+                file: 0,
+                row: 0,
+                col: 35,
+                len: 0
+            }))
+        );
     }
 
     #[test]
     fn selecting() {
-        let input = ", : add &";
-        let mut lexer = mylexer::Lexer::new(input);
+        let input = ", : add @";
+        let mut lexer = Lexer::new(input);
 
+        assert_eq!(sel_token(&mut lexer, Token::Comma).unwrap().1, Token::Comma);
+        assert_eq!(sel_token(&mut lexer, Token::Colon).unwrap().1, Token::Colon);
         assert_eq!(
-            sel_token(lexer.next(), Token::Comma).unwrap().1,
-            Token::Comma
-        );
-        assert_eq!(
-            sel_token(lexer.next(), Token::Colon).unwrap().1,
-            Token::Colon
-        );
-        assert_eq!(
-            sel_token(lexer.next(), Token::Colon),
-            Err(ParserError::Incomplete(Loc { row: 0, col: 4 }))
+            sel_token(&mut lexer, Token::Colon),
+            Err(ParserError::Incomplete(Span {
+                file: 0,
+                row: 0,
+                col: 4,
+                len: 3
+            }))
         );
         assert_eq!(
-            sel_token(lexer.next(), Token::Colon),
-            Err(ParserError::InvalidToken(Loc { row: 0, col: 8 }))
+            sel_token(&mut lexer, Token::Colon),
+            Err(ParserError::InvalidToken(Span {
+                file: 0,
+                row: 0,
+                col: 8,
+                len: 1
+            }))
         );
     }
 
     #[test]
     fn register() {
         let input = "$zero \n 12 $one";
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
 
-        assert_eq!(get_register(lexer.next()).unwrap(), 0);
+        assert_eq!(get_register(&mut lexer).unwrap(), 0);
         assert_eq!(
-            get_register(lexer.next()),
-            Err(ParserError::Incomplete(Loc { row: 1, col: 1 }))
+            get_register(&mut lexer),
+            Err(ParserError::Incomplete(Span {
+                file: 0,
+                row: 1,
+                col: 1,
+                len: 2
+            }))
         );
         assert_eq!(
-            get_register(lexer.next()),
-            Err(ParserError::InvalidToken(Loc { row: 1, col: 4 }))
+            get_register(&mut lexer),
+            Err(ParserError::InvalidToken(Span {
+                file: 0,
+                row: 1,
+                col: 4,
+                len: 1
+            }))
         );
     }
 
     #[test]
     fn immediate() {
         let input = "-12 0xf $one";
-        let mut lexer = mylexer::Lexer::new(input);
+        let mut lexer = Lexer::new(input);
+
+        assert_eq!(
+            get_immediate(&mut lexer, &Constants::new(), -12..=12).unwrap(),
+            -12
+        );
+        assert_eq!(
+            get_immediate(&mut lexer, &Constants::new(), 0..=12),
+            Err(ParserError::ImmediateOutOfRange(
+                15,
+                0..=12,
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 4,
+                    len: 3
+                }
+            ))
+        );
+        assert_eq!(
+            get_register(&mut lexer),
+            Err(ParserError::InvalidToken(Span {
+                file: 0,
+                row: 0,
+                col: 8,
+                len: 1
+            }))
+        );
+    }
+
+    #[test]
+    fn expression_or_imm() {
+        let input = "-12, greg + 4, 3 << SHIFT, @";
+        let mut lexer = Lexer::new(input);
+
+        let mut constants = Constants::new();
+        constants.insert(
+            "SHIFT".to_string(),
+            ConstantNode {
+                identifier: "SHIFT".to_string(),
+                value: 2,
+                redefinable: false,
+                span: Span::default(),
+            },
+        );
 
-        assert_eq!(get_immediate(lexer.next()).unwrap(), -12);
-        assert_eq!(get_immediate(lexer.next()).unwrap(), 15);
         assert_eq!(
-            get_register(lexer.next()),
-            Err(ParserError::InvalidToken(Loc { row: 0, col: 8 }))
+            get_expression_or_imm(&mut lexer, &constants, WORD_RANGE).unwrap(),
+            (None, -12)
+        );
+        sel_token(&mut lexer, Token::Comma).unwrap();
+        assert_eq!(
+            get_expression_or_imm(&mut lexer, &constants, 0..=0).unwrap(),
+            (
+                Some(Expr::Binary(
+                    Op::Add,
+                    Box::new(Expr::Identifier("greg".to_string())),
+                    Box::new(Expr::Value(4))
+                )),
+                0
+            )
+        );
+        sel_token(&mut lexer, Token::Comma).unwrap();
+        assert_eq!(
+            get_expression_or_imm(&mut lexer, &constants, 0..=10),
+            Err(ParserError::ImmediateOutOfRange(
+                12,
+                0..=10,
+                Span {
+                    file: 0,
+                    row: 0,
+                    col: 15,
+                    len: 10
+                }
+            ))
+        );
+        sel_token(&mut lexer, Token::Comma).unwrap();
+        assert_eq!(
+            get_register(&mut lexer),
+            Err(ParserError::InvalidToken(Span {
+                file: 0,
+                row: 0,
+                col: 27,
+                len: 1
+            }))
         );
     }
 
     #[test]
-    fn identifier_or_imm() {
-        let input = "-12 greg +";
-        let mut lexer = mylexer::Lexer::new(input);
+    fn peeking() {
+        let input = "add $zero @";
+        let mut lexer = Lexer::new(input);
 
         assert_eq!(
-            get_identifier_or_imm(lexer.next()).unwrap(),
-            ("".to_owned(), -12)
+            lexer.peek().cloned().unwrap().unwrap().1,
+            Token::Instruction(Instr::Add)
         );
         assert_eq!(
-            get_identifier_or_imm(lexer.next()).unwrap(),
-            ("greg".to_owned(), 0)
+            read_token(&mut lexer).unwrap().1,
+            Token::Instruction(Instr::Add)
         );
+        assert_eq!(get_register(&mut lexer).unwrap(), 0);
+
+        lexer.step_back();
+        assert_eq!(get_register(&mut lexer).unwrap(), 0);
         assert_eq!(
-            get_register(lexer.next()),
-            Err(ParserError::InvalidToken(Loc { row: 0, col: 9 }))
+            read_token(&mut lexer),
+            Err(ParserError::InvalidToken(Span {
+                file: 0,
+                row: 0,
+                col: 10,
+                len: 1
+            }))
         );
+        assert_eq!(lexer.peek(), None);
     }
 }
@@ -1,77 +1,251 @@
 use crate::types::*;
-use crate::ProgramTree;
+use crate::{MemoryMap, ProgramTree};
+use std::ops::Range;
+
+// writer for raw binary images
+mod printraw;
+pub use printraw::*;
+
+// writer for verilog $readmemh and $readmemb files
+mod printreadmem;
+pub use printreadmem::*;
+
+// writers for xilinx COE and intel MIF block RAM initialisation files
+mod printbram;
+pub use printbram::*;
+
+// writer for motorola S-records
+mod printsrec;
+pub use printsrec::*;
+
+// writer for ELF32 executables
+mod printelf;
+pub use printelf::*;
+
+/// # Endianness
+///
+/// selects the order bytes and halfwords are packed into words in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Endianness {
+    /// the first byte goes into the least significant bits
+    Little,
+    /// the first byte goes into the most significant bits
+    Big,
+}
 
 /// receives an abstract program tree, handles converting it to binary form
+///
+/// bytes and halfwords are packed in little endian order,
+/// the segments start at the bases of the default memory map
 pub fn print_binary(tree: ProgramTree) -> ProgramBinary {
+    print_binary_with(tree, Endianness::Little, &MemoryMap::default())
+}
+
+/// receives an abstract program tree, handles converting it to binary form,
+/// packing bytes and halfwords in the given order
+///
+/// each segment of the binary starts at its base in memory
+pub fn print_binary_with(
+    tree: ProgramTree,
+    endianness: Endianness,
+    memory: &MemoryMap,
+) -> ProgramBinary {
     // create a new binary
     let mut binary = ProgramBinary {
         data: Vec::new(),
         instructions: Vec::new(),
     };
 
-    // convert data nodes
+    // convert data nodes, each placed at the word its address refers to
     for datanode in tree.data {
-        match datanode.block {
-            // addr nodes work directly on addresses, usually memory mapped IO
-            // so they are ignored when generating memory files
-            Bl::Addr => (),
-
-            // word nodes are directly placed in memory
-            Bl::Word => binary.data.push(datanode.data as u32),
-
-            // each space node represents [num] words. Here they are initialized.
-            Bl::Space => {
-                for _i in 0..datanode.num {
-                    binary.data.push(0);
-                }
-            }
-            b => panic!("Invalid block: {:?}. This is a parser bug", b),
-            //this should never happen if the program logic is correct
+        // addr nodes work directly on addresses, usually memory mapped IO
+        // so they are ignored when generating memory files
+        if datanode.block == Bl::Addr {
+            continue;
         }
+
+        let index = (datanode.address - memory.data_base) as usize / 4;
+        place(&mut binary.data, index, &data_words(&datanode, endianness));
     }
 
     for instrnode in tree.instructions {
-        // assemble instruction
-        let mut instruction = 0;
-        instruction |= (match instrnode.op {
-            // add opcode
-            Instr::And => 0b0000,
-            Instr::Or => 0b0001,
-            Instr::Xor => 0b0010,
-            Instr::Not => 0b0011,
-
-            Instr::Add => 0b0100,
-            Instr::Sub => 0b0101,
-            Instr::Cmp => 0b0110,
-
-            Instr::J => 0b0111,
-            Instr::Beq => 0b1000,
-            Instr::Bne => 0b1001,
-
-            Instr::Sl => 0b1010,
-            Instr::Sr => 0b1011,
-            Instr::Addi => 0b1100,
-            Instr::Lui => 0b1101,
-
-            Instr::Lw => 0b1110,
-            Instr::Sw => 0b1111,
-            i => panic!(
-                "Pseudoinstruction not handled: {:?}. This is a parser bug",
-                i
-            ),
-            //this should never happen if the program logic is correct
-        }) << 28;
+        let instruction = encode(&instrnode);
+        let index = (instrnode.address - memory.text_base) as usize / 4;
+        place(&mut binary.instructions, index, &[instruction]);
+    }
+
+    binary
+}
 
-        instruction |= ((instrnode.rd as u32) & 0b1111) << 24; // add Rd
-        instruction |= ((instrnode.ra as u32) & 0b1111) << 20; // add Ra
-        instruction |= ((instrnode.rb as u32) & 0b1111) << 16; // add Rb
+/// the words a data node fills memory with, bytes and halfwords packed in the given order
+///
+/// addr nodes take up no memory, so they have no words
+pub fn data_words(datanode: &DataNode, endianness: Endianness) -> Vec<u32> {
+    match &datanode.block {
+        Bl::Addr => vec![],
+
+        // word nodes are directly placed in memory, repeated [num] times
+        Bl::Word => vec![datanode.data as u32; datanode.num as usize],
 
-        instruction |= (instrnode.imm as u32) & 0xFFFF; // add immediate
+        // each space node represents [num] words. Here they are initialized.
+        Bl::Space => vec![0; datanode.num as usize],
 
-        binary.instructions.push(instruction);
+        // byte and halfword nodes are packed into words, the last one padded with zeros
+        Bl::Ascii | Bl::Asciiz | Bl::Byte => pack(&datanode.payload, 8, endianness),
+        Bl::Half => pack(&datanode.payload, 16, endianness),
+
+        b => panic!("Invalid block: {:?}. This is a parser bug", b),
+        //this should never happen if the program logic is correct
     }
+}
 
-    binary
+/// assemble a single instruction into the word it is stored as
+pub fn encode(instrnode: &InstructionNode) -> u32 {
+    let mut instruction = 0;
+    instruction |= (match &instrnode.op {
+        // add opcode
+        Instr::And => 0b0000,
+        Instr::Or => 0b0001,
+        Instr::Xor => 0b0010,
+        Instr::Not => 0b0011,
+
+        Instr::Add => 0b0100,
+        Instr::Sub => 0b0101,
+        Instr::Cmp => 0b0110,
+
+        Instr::J => 0b0111,
+        Instr::Beq => 0b1000,
+        Instr::Bne => 0b1001,
+
+        Instr::Sl => 0b1010,
+        Instr::Sr => 0b1011,
+        Instr::Addi => 0b1100,
+        Instr::Lui => 0b1101,
+
+        Instr::Lw => 0b1110,
+        Instr::Sw => 0b1111,
+        i => panic!(
+            "Pseudoinstruction not handled: {:?}. This is a parser bug",
+            i
+        ),
+        //this should never happen if the program logic is correct
+    }) << 28;
+
+    instruction |= ((instrnode.rd as u32) & 0b1111) << 24; // add Rd
+    instruction |= ((instrnode.ra as u32) & 0b1111) << 20; // add Ra
+    instruction |= ((instrnode.rb as u32) & 0b1111) << 16; // add Rb
+
+    instruction |= (instrnode.imm as u32) & 0xFFFF; // add immediate
+
+    instruction
+}
+
+/// write words into memory starting at index,
+/// filling any gap left by .org or .align with zeros
+fn place(memory: &mut Vec<u32>, index: usize, words: &[u32]) {
+    if memory.len() < index + words.len() {
+        memory.resize(index + words.len(), 0);
+    }
+    memory[index..index + words.len()].copy_from_slice(words);
+}
+
+/// the runs of word indices that nodes were placed at in the text and data memories,
+/// in order of address
+///
+/// padding left by .org and .align and the zeros reserved with .space belong to no run
+pub fn placed_runs(
+    tree: &ProgramTree,
+    memory: &MemoryMap,
+) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let text = tree.instructions.iter().map(|node| {
+        let index = (node.address - memory.text_base) as usize / 4;
+        index..index + 1
+    });
+    let data = tree
+        .data
+        .iter()
+        .filter(|node| !matches!(node.block, Bl::Addr | Bl::Space))
+        .map(|node| {
+            let index = (node.address - memory.data_base) as usize / 4;
+            index..index + node.num as usize
+        });
+
+    (merge_runs(text), merge_runs(data))
+}
+
+/// sort ranges and join those that touch or overlap into runs
+fn merge_runs(ranges: impl Iterator<Item = Range<usize>>) -> Vec<Range<usize>> {
+    let mut ranges: Vec<_> = ranges.filter(|range| !range.is_empty()).collect();
+    ranges.sort_by_key(|range| range.start);
+
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match runs.last_mut() {
+            Some(run) if range.start <= run.end => run.end = run.end.max(range.end),
+            _ => runs.push(range),
+        }
+    }
+
+    runs
+}
+
+/// pack elements of the given width in bits into words
+fn pack(payload: &[u32], width: usize, endianness: Endianness) -> Vec<u32> {
+    let per_word = 32 / width;
+
+    payload
+        .chunks(per_word)
+        .map(|chunk| {
+            chunk.iter().enumerate().fold(0, |word, (i, element)| {
+                let shift = match endianness {
+                    Endianness::Little => width * i,
+                    Endianness::Big => 32 - width * (i + 1),
+                };
+                word | (element << shift)
+            })
+        })
+        .collect()
+}
+
+/// # Intel HEX addressing mode
+///
+/// selects the record type used to reach addresses above 0xFFFF
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HexAddressing {
+    /// type 04 extended linear address records, covers the whole 32 bit address space
+    Linear,
+    /// type 02 extended segment address records, limited to addresses below 0x10_0000
+    Segment,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PrinterError {
+    AddressOutOfRange(u32),
+    SegmentsOverlap(u32),
+    DepthExceeded(u32, u32),
+    OffsetOutOfRange(u32, u32),
+}
+
+impl std::fmt::Display for PrinterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::AddressOutOfRange(addr) => {
+                write!(f, "Address cannot be represented in output: 0x{addr:08X}")
+            }
+            Self::SegmentsOverlap(offset) => write!(
+                f,
+                "Instructions reach into the data at physical word address 0x{offset:X}"
+            ),
+            Self::DepthExceeded(words, depth) => write!(
+                f,
+                "Memory too shallow: {words} words do not fit in a depth of {depth}"
+            ),
+            Self::OffsetOutOfRange(offset, words) => write!(
+                f,
+                "Physical data offset 0x{offset:X} lies beyond the 0x{words:X} words of text memory"
+            ),
+        }
+    }
 }
 
 /// convert the binary to intel HEX format for uploading to hardware
@@ -80,28 +254,77 @@ pub fn print_binary(tree: ProgramTree) -> ProgramBinary {
 /// - the memory of the target is word addressable
 /// - the target has a virtual memory interface
 ///   that remaps 0x1000_000 to the contents of offset
+///
+/// addresses above 0xFFFF are reached with extended linear address records
 pub fn print_hex(binary: ProgramBinary, offset: u32) -> String {
+    match print_hex_with(binary, offset, HexAddressing::Linear) {
+        Ok(hex) => hex,
+        Err(e) => panic!("{e}. Linear addressing covers all addresses, this is a printer bug"),
+    }
+}
+
+/// convert the binary to intel HEX format, selecting the extended address record type
+///
+/// an extended address record is emitted whenever the upper 16 bits of the address change
+pub fn print_hex_with(
+    binary: ProgramBinary,
+    offset: u32,
+    addressing: HexAddressing,
+) -> Result<String, PrinterError> {
     let mut hex = String::new();
 
-    let mut addr = 0;
+    // the upper address bits implied at the start of the file are all zero
+    let mut upper = 0;
 
-    for word in binary.instructions {
-        hex.push_str(&print_hex_line(addr, word));
-        addr += 1;
-    }
+    let words = binary
+        .instructions
+        .into_iter()
+        .enumerate()
+        .map(|(i, word)| (i as u32, word))
+        .chain(
+            binary
+                .data
+                .into_iter()
+                .enumerate()
+                .map(|(i, word)| (offset.wrapping_add(i as u32), word)),
+        );
 
-    addr = offset;
-    for word in binary.data {
+    for (addr, word) in words {
+        if addr >> 16 != upper {
+            upper = addr >> 16;
+            hex.push_str(&print_hex_address_line(addr, addressing)?);
+        }
         hex.push_str(&print_hex_line(addr, word));
-        addr += 1;
     }
 
     hex.push_str(":00000001FF\n");
 
-    hex
+    Ok(hex)
+}
+
+/// prints an extended address record selecting the upper 16 bits of addr
+fn print_hex_address_line(addr: u32, addressing: HexAddressing) -> Result<String, PrinterError> {
+    let (record_type, base) = match addressing {
+        HexAddressing::Linear => (0x04, addr >> 16),
+        // segment records hold the base address divided by 16
+        HexAddressing::Segment if addr < 0x10_0000 => (0x02, (addr >> 16) << 12),
+        HexAddressing::Segment => return Err(PrinterError::AddressOutOfRange(addr)),
+    };
+
+    let mut checksum = 0x02 + record_type + ((base) & 0xff) + ((base >> 8) & 0xff);
+
+    checksum = !checksum + 1;
+    checksum &= 0xff;
+
+    Ok(format!(
+        ":020000{:02X}{:04X}{:02X}\n",
+        record_type, base, checksum
+    ))
 }
 
 /// prints a single intel HEX formatted line
+///
+/// only the bottom 16 bits of the address are printed
 fn print_hex_line(addr: u32, data: u32) -> String {
     //https://en.wikipedia.org/wiki/Intel_HEX
     let mut checksum = 0x04
@@ -115,22 +338,63 @@ fn print_hex_line(addr: u32, data: u32) -> String {
     checksum = !checksum + 1;
     checksum &= 0xff;
 
-    format!(":04{:04X}00{:08X}{:02X}\n", addr, data, checksum)
+    format!(":04{:04X}00{:08X}{:02X}\n", addr & 0xffff, data, checksum)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::expr::Expr;
+    use crate::Span;
+
+    // tests if bytes and halfwords are packed in the selected order
+    #[test]
+    fn packing_test() {
+        let node = |address, block, payload: Vec<u32>| DataNode {
+            identifier: "".to_string(),
+            label_span: Span::default(),
+            address,
+            block,
+            data: 0,
+            data_expr: None,
+            num: 0,
+            payload,
+            span: Span::default(),
+        };
+
+        let tree = ProgramTree {
+            constants: vec![],
+            data: vec![
+                node(0x1000_0000, Bl::Ascii, vec![0x48, 0x69, 0x21, 0x0a, 0x41]),
+                node(0x1000_0008, Bl::Half, vec![0x1234, 0x5678, 0x9abc]),
+            ],
+            instructions: vec![],
+        };
+
+        assert_eq!(
+            print_binary_with(tree.clone(), Endianness::Little, &MemoryMap::default()).data,
+            vec![0x0a21_6948, 0x41, 0x5678_1234, 0x9abc]
+        );
+        assert_eq!(
+            print_binary_with(tree, Endianness::Big, &MemoryMap::default()).data,
+            vec![0x4869_210a, 0x4100_0000, 0x1234_5678, 0x9abc_0000]
+        );
+    }
 
     #[test]
     fn printer_test() {
         let tree = ProgramTree {
+            constants: vec![],
             data: vec![DataNode {
                 identifier: "number".to_string(),
-                address: 0x1000_0004,
+                label_span: Span::default(),
+                address: 0x1000_0000,
                 block: Bl::Word,
                 data: 5,
+                data_expr: None,
+                payload: vec![],
                 num: 1,
+                span: Span::default(),
             }],
             instructions: vec![
                 InstructionNode {
@@ -140,8 +404,11 @@ mod tests {
                     rb: 0,
                     imm: 0x1000,
                     identifier: "beginning".to_string(),
-                    imm_identifier: "number".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(Expr::Identifier("number".to_string())),
                     address: 0,
+                    placement: None,
+                    span: Span::default(),
                 },
                 InstructionNode {
                     op: Instr::Addi,
@@ -150,8 +417,11 @@ mod tests {
                     rb: 0,
                     imm: 0x0004,
                     identifier: "beginning".to_string(),
-                    imm_identifier: "number".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(Expr::Identifier("number".to_string())),
                     address: 4,
+                    placement: None,
+                    span: Span::default(),
                 },
                 InstructionNode {
                     op: Instr::Beq,
@@ -160,8 +430,11 @@ mod tests {
                     rb: 6,
                     imm: -4,
                     identifier: "".to_string(),
-                    imm_identifier: "beginning".to_string(),
+                    label_span: Span::default(),
+                    imm_expr: Some(Expr::Identifier("beginning".to_string())),
                     address: 8,
+                    placement: None,
+                    span: Span::default(),
                 },
             ],
         };
@@ -174,6 +447,48 @@ mod tests {
         assert_eq!(print_binary(tree), bin);
     }
 
+    // tests if gaps left by .org and .align are filled with zeros
+    #[test]
+    fn gap_test() {
+        let word = |address, data| DataNode {
+            identifier: "".to_string(),
+            label_span: Span::default(),
+            address,
+            block: Bl::Word,
+            data,
+            data_expr: None,
+            payload: vec![],
+            num: 1,
+            span: Span::default(),
+        };
+        let instruction = |address| InstructionNode {
+            op: Instr::Addi,
+            rd: 1,
+            ra: 0,
+            rb: 0,
+            imm: 1,
+            identifier: "".to_string(),
+            label_span: Span::default(),
+            imm_expr: None,
+            address,
+            placement: None,
+            span: Span::default(),
+        };
+
+        let tree = ProgramTree {
+            constants: vec![],
+            data: vec![word(0x1000_0010, 2), word(0x1000_0004, 1)],
+            instructions: vec![instruction(0), instruction(0xc)],
+        };
+
+        let bin = ProgramBinary {
+            data: vec![0, 1, 0, 0, 2],
+            instructions: vec![0xC100_0001, 0, 0, 0xC100_0001],
+        };
+
+        assert_eq!(print_binary(tree), bin);
+    }
+
     #[test]
     fn hex_line_test() {
         assert_eq!(print_hex_line(4, 0xdeadbeef), *":04000400DEADBEEFC0\n");
@@ -191,4 +506,78 @@ mod tests {
 
         assert_eq!(print_hex(bin, 0x400), hex);
     }
+
+    #[test]
+    fn hex_address_line_test() {
+        assert_eq!(
+            print_hex_address_line(0x0001_0000, HexAddressing::Linear),
+            Ok(":020000040001F9\n".to_string())
+        );
+        assert_eq!(
+            print_hex_address_line(0x1234_5678, HexAddressing::Linear),
+            Ok(":020000041234B4\n".to_string())
+        );
+        assert_eq!(
+            print_hex_address_line(0x0001_0000, HexAddressing::Segment),
+            Ok(":020000021000EC\n".to_string())
+        );
+        assert_eq!(
+            print_hex_address_line(0x0010_0000, HexAddressing::Segment),
+            Err(PrinterError::AddressOutOfRange(0x0010_0000))
+        );
+    }
+
+    #[test]
+    fn hex_printer_crossover_test() {
+        // data crosses the 64K word boundary, instructions stay below it
+        let bin = ProgramBinary {
+            data: vec![1, 2, 3],
+            instructions: vec![0xD100_1000],
+        };
+
+        let hex = ":04000000D10010001B\n\
+                   :04FFFE0000000001FE\n\
+                   :04FFFF0000000002FC\n\
+                   :020000040001F9\n\
+                   :0400000000000003F9\n\
+                   :00000001FF\n";
+
+        assert_eq!(print_hex(bin.clone(), 0xFFFE), hex);
+
+        let hex = ":04000000D10010001B\n\
+                   :04FFFE0000000001FE\n\
+                   :04FFFF0000000002FC\n\
+                   :020000021000EC\n\
+                   :0400000000000003F9\n\
+                   :00000001FF\n";
+
+        assert_eq!(
+            print_hex_with(bin.clone(), 0xFFFE, HexAddressing::Segment),
+            Ok(hex.to_string())
+        );
+
+        assert_eq!(
+            print_hex_with(bin, 0xFFFF_FFFE, HexAddressing::Segment),
+            Err(PrinterError::AddressOutOfRange(0xFFFF_FFFE))
+        );
+    }
+
+    #[test]
+    fn hex_printer_return_to_low_addresses_test() {
+        // instructions run past 64K words, data lies back in the low region
+        let bin = ProgramBinary {
+            data: vec![5],
+            instructions: vec![0; 0x10001],
+        };
+
+        let hex = print_hex(bin, 0x400);
+        let mut lines = hex.lines().skip(0x10000);
+
+        assert_eq!(lines.next(), Some(":020000040001F9"));
+        assert_eq!(lines.next(), Some(":0400000000000000FC"));
+        assert_eq!(lines.next(), Some(":020000040000FA"));
+        assert_eq!(lines.next(), Some(":0404000000000005F3"));
+        assert_eq!(lines.next(), Some(":00000001FF"));
+        assert_eq!(lines.next(), None);
+    }
 }
@@ -0,0 +1,104 @@
+use super::*;
+
+/// number of words in a memory holding words, padded up to depth if there is one
+fn depth_of(words: &[u32], depth: Option<u32>) -> Result<usize, PrinterError> {
+    match depth {
+        Some(depth) if words.len() > depth as usize => {
+            Err(PrinterError::DepthExceeded(words.len() as u32, depth))
+        }
+        Some(depth) => Ok(depth as usize),
+        // a memory holds at least one word
+        None => Ok(words.len().max(1)),
+    }
+}
+
+/// convert the words of one memory to a Xilinx COE file for Vivado block RAM cores,
+/// padded with zeros up to depth
+pub fn print_coe(words: &[u32], depth: Option<u32>) -> Result<String, PrinterError> {
+    let depth = depth_of(words, depth)?;
+
+    let mut coe = String::from("memory_initialization_radix=16;\nmemory_initialization_vector=\n");
+
+    let vector: Vec<String> = (0..depth)
+        .map(|i| format!("{:08X}", words.get(i).copied().unwrap_or(0)))
+        .collect();
+    coe.push_str(&vector.join(",\n"));
+    coe.push_str(";\n");
+
+    Ok(coe)
+}
+
+/// convert the words of one memory to an Intel MIF file for Quartus memory cores,
+/// with the words past the program up to depth set to zero in a single range
+pub fn print_mif(words: &[u32], depth: Option<u32>) -> Result<String, PrinterError> {
+    let depth = depth_of(words, depth)?;
+
+    let mut mif = format!(
+        "DEPTH = {depth};\nWIDTH = 32;\nADDRESS_RADIX = HEX;\nDATA_RADIX = HEX;\nCONTENT\nBEGIN\n"
+    );
+
+    for (addr, word) in words.iter().enumerate() {
+        mif.push_str(&format!("{addr:X} : {word:08X};\n"));
+    }
+    match depth - words.len() {
+        0 => (),
+        1 => mif.push_str(&format!("{:X} : 00000000;\n", words.len())),
+        _ => mif.push_str(&format!(
+            "[{:X}..{:X}] : 00000000;\n",
+            words.len(),
+            depth - 1
+        )),
+    }
+
+    mif.push_str("END;\n");
+
+    Ok(mif)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bram_test() {
+        let words = [0xC900_2000, 0x5];
+
+        assert_eq!(
+            print_coe(&words, None),
+            Ok(
+                "memory_initialization_radix=16;\nmemory_initialization_vector=\n\
+                C9002000,\n00000005;\n"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            print_coe(&words, Some(3)),
+            Ok(
+                "memory_initialization_radix=16;\nmemory_initialization_vector=\n\
+                C9002000,\n00000005,\n00000000;\n"
+                    .to_string()
+            )
+        );
+
+        let header = "WIDTH = 32;\nADDRESS_RADIX = HEX;\nDATA_RADIX = HEX;\nCONTENT\nBEGIN\n";
+        assert_eq!(
+            print_mif(&words, Some(0x20)),
+            Ok(format!(
+                "DEPTH = 32;\n{header}0 : C9002000;\n1 : 00000005;\n[2..1F] : 00000000;\nEND;\n"
+            ))
+        );
+        assert_eq!(
+            print_mif(&[], None),
+            Ok(format!("DEPTH = 1;\n{header}0 : 00000000;\nEND;\n"))
+        );
+
+        assert_eq!(
+            print_mif(&words, Some(1)),
+            Err(PrinterError::DepthExceeded(2, 1))
+        );
+        assert_eq!(
+            print_coe(&words, Some(1)),
+            Err(PrinterError::DepthExceeded(2, 1))
+        );
+    }
+}
@@ -0,0 +1,483 @@
+use super::*;
+use crate::{Segment, SourceFile, SymbolTable};
+
+/// machine number written to the ELF header
+///
+/// greg has no number assigned, this one spells "GR"
+pub const EM_GREG: u16 = 0x4752;
+
+// section header indices, in the order the sections are written
+const TEXT: u16 = 1;
+const DATA: u16 = 2;
+const BSS: u16 = 3;
+const STRTAB: u16 = 5;
+const SHSTRTAB: u16 = 6;
+
+/// sizes of the ELF header, a program header, a section header and a symbol
+const EHDR_SIZE: u32 = 52;
+const PHDR_SIZE: u32 = 32;
+const SHDR_SIZE: u32 = 40;
+const SYM_SIZE: u32 = 16;
+
+/// convert the program to an ELF32 executable
+///
+/// .text and .data hold the words of each memory, stored in the given byte order.
+/// The zeroes reserved with .space after the last initialised data word go to .bss.
+/// Every symbol of the table is global, absolute ones are not tied to a section.
+/// If the sources are given, a DWARF .debug_line section maps instructions back to them
+pub fn print_elf(
+    tree: &ProgramTree,
+    table: &SymbolTable,
+    sources: Option<&[SourceFile]>,
+    endianness: Endianness,
+    memory: &MemoryMap,
+) -> Vec<u8> {
+    let binary = print_binary_with(tree.clone(), endianness, memory);
+
+    // words after the end of the last node holding values are all reserved zeroes
+    let initialised = tree
+        .data
+        .iter()
+        .filter(|n| !matches!(n.block, Bl::Addr | Bl::Space))
+        .map(|n| (n.address - memory.data_base) as usize / 4 + data_words(n, endianness).len())
+        .max()
+        .unwrap_or(0);
+    let bss_base = memory.data_base as u64 + 4 * initialised as u64;
+    let bss_size = 4 * (binary.data.len() - initialised) as u32;
+
+    let text = print_raw(&binary.instructions, endianness);
+    let data = print_raw(&binary.data[..initialised], endianness);
+
+    // the symbol table starts with the null symbol
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; SYM_SIZE as usize];
+    for symbol in table {
+        let (kind, section) = match symbol.segment {
+            Segment::Text => (STT_FUNC, TEXT),
+            Segment::Data if (symbol.value as u64) < bss_base => (STT_OBJECT, DATA),
+            Segment::Data => (STT_OBJECT, BSS),
+            Segment::Absolute => (STT_NOTYPE, SHN_ABS),
+        };
+        let name = add_string(&mut strtab, &symbol.name);
+        word(&mut symtab, name, endianness);
+        word(&mut symtab, symbol.value as u32, endianness);
+        word(&mut symtab, symbol.size, endianness);
+        symtab.push(STB_GLOBAL << 4 | kind);
+        symtab.push(0);
+        half(&mut symtab, section, endianness);
+    }
+
+    let mut shstrtab = vec![0];
+    let names: Vec<u32> = [
+        ".text",
+        ".data",
+        ".bss",
+        ".symtab",
+        ".strtab",
+        ".shstrtab",
+        ".debug_line",
+    ]
+    .iter()
+    .map(|name| add_string(&mut shstrtab, name))
+    .collect();
+
+    let debug_line = sources.map(|sources| print_debug_line(tree, sources, endianness));
+
+    // a loadable segment for each memory that takes up space
+    let mut segments = Vec::new();
+    if !text.is_empty() {
+        let size = text.len() as u32;
+        segments.push((memory.text_base, 0, size, size, PF_R | PF_X));
+    }
+    if !data.is_empty() || bss_size != 0 {
+        // the physical address is where the data lies in the image of print_image
+        let physical = 4 * memory.physical_data_offset;
+        let size = data.len() as u32;
+        segments.push((
+            memory.data_base,
+            physical,
+            size,
+            size + bss_size,
+            PF_R | PF_W,
+        ));
+    }
+
+    // contents follow the headers, every part padded to start on a word boundary
+    let after = |offset: u32, part: &[u8]| (offset + part.len() as u32).next_multiple_of(4);
+    let text_offset = EHDR_SIZE + PHDR_SIZE * segments.len() as u32;
+    let data_offset = after(text_offset, &text);
+    let symtab_offset = after(data_offset, &data);
+    let strtab_offset = after(symtab_offset, &symtab);
+    let shstrtab_offset = after(strtab_offset, &strtab);
+    let debug_line_offset = after(shstrtab_offset, &shstrtab);
+    let shoff = match &debug_line {
+        Some(debug_line) => after(debug_line_offset, debug_line),
+        None => debug_line_offset,
+    };
+    let shnum = if debug_line.is_some() { 8 } else { 7 };
+
+    let mut elf = Vec::new();
+
+    // identification: magic, 32 bit class, byte order, version
+    elf.extend(b"\x7FELF");
+    elf.push(ELFCLASS32);
+    elf.push(match endianness {
+        Endianness::Little => ELFDATA2LSB,
+        Endianness::Big => ELFDATA2MSB,
+    });
+    elf.push(EV_CURRENT);
+    elf.resize(16, 0);
+
+    half(&mut elf, ET_EXEC, endianness);
+    half(&mut elf, EM_GREG, endianness);
+    word(&mut elf, EV_CURRENT as u32, endianness);
+    // the startup code runs from the start of the instruction memory
+    word(&mut elf, memory.text_base, endianness);
+    word(
+        &mut elf,
+        if segments.is_empty() { 0 } else { EHDR_SIZE },
+        endianness,
+    );
+    word(&mut elf, shoff, endianness);
+    word(&mut elf, 0, endianness);
+    half(&mut elf, EHDR_SIZE as u16, endianness);
+    half(&mut elf, PHDR_SIZE as u16, endianness);
+    half(&mut elf, segments.len() as u16, endianness);
+    half(&mut elf, SHDR_SIZE as u16, endianness);
+    half(&mut elf, shnum, endianness);
+    half(&mut elf, SHSTRTAB, endianness);
+
+    let mut offset = text_offset;
+    for (address, physical, file_size, size, flags) in segments {
+        for field in [
+            PT_LOAD, offset, address, physical, file_size, size, flags, 4,
+        ] {
+            word(&mut elf, field, endianness);
+        }
+        offset += file_size;
+    }
+
+    let parts = [
+        (text_offset, Some(&text)),
+        (data_offset, Some(&data)),
+        (symtab_offset, Some(&symtab)),
+        (strtab_offset, Some(&strtab)),
+        (shstrtab_offset, Some(&shstrtab)),
+        (debug_line_offset, debug_line.as_ref()),
+        (shoff, None),
+    ];
+    for (offset, part) in parts {
+        elf.resize(offset as usize, 0);
+        elf.extend(part.into_iter().flatten());
+    }
+
+    // name, type, flags, address, offset, size, link, info, alignment, entry size
+    let mut sections = vec![[0; 10]];
+    sections.push([
+        names[0],
+        SHT_PROGBITS,
+        SHF_ALLOC | SHF_EXECINSTR,
+        memory.text_base,
+        text_offset,
+        text.len() as u32,
+        0,
+        0,
+        4,
+        0,
+    ]);
+    sections.push([
+        names[1],
+        SHT_PROGBITS,
+        SHF_ALLOC | SHF_WRITE,
+        memory.data_base,
+        data_offset,
+        data.len() as u32,
+        0,
+        0,
+        4,
+        0,
+    ]);
+    // .bss takes up no room in the file
+    sections.push([
+        names[2],
+        SHT_NOBITS,
+        SHF_ALLOC | SHF_WRITE,
+        // only an empty .bss can start at the end of the address space
+        bss_base as u32,
+        symtab_offset,
+        bss_size,
+        0,
+        0,
+        4,
+        0,
+    ]);
+    // info holds the index of the first global symbol, all but the null symbol are global
+    sections.push([
+        names[3],
+        SHT_SYMTAB,
+        0,
+        0,
+        symtab_offset,
+        symtab.len() as u32,
+        STRTAB as u32,
+        1,
+        4,
+        SYM_SIZE,
+    ]);
+    sections.push([
+        names[4],
+        SHT_STRTAB,
+        0,
+        0,
+        strtab_offset,
+        strtab.len() as u32,
+        0,
+        0,
+        1,
+        0,
+    ]);
+    sections.push([
+        names[5],
+        SHT_STRTAB,
+        0,
+        0,
+        shstrtab_offset,
+        shstrtab.len() as u32,
+        0,
+        0,
+        1,
+        0,
+    ]);
+    if let Some(debug_line) = &debug_line {
+        sections.push([
+            names[6],
+            SHT_PROGBITS,
+            0,
+            0,
+            debug_line_offset,
+            debug_line.len() as u32,
+            0,
+            0,
+            1,
+            0,
+        ]);
+    }
+
+    for section in sections {
+        for field in section {
+            word(&mut elf, field, endianness);
+        }
+    }
+
+    elf
+}
+
+/// print a DWARF version 2 line number program, with a row for every instruction
+///
+/// files are numbered in the order of the sources, starting at 1
+fn print_debug_line(tree: &ProgramTree, sources: &[SourceFile], endianness: Endianness) -> Vec<u8> {
+    // minimum instruction length, default is_stmt, line base, line range, opcode base
+    // and the number of operands of each standard opcode
+    let mut header = vec![4, 1, -5i8 as u8, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+    // no include directories, then the files, each without directory, time and length
+    header.push(0);
+    for source in sources {
+        header.extend(source.name.as_bytes());
+        header.extend([0, 0, 0, 0]);
+    }
+    header.push(0);
+
+    let mut nodes: Vec<_> = tree.instructions.iter().collect();
+    nodes.sort_by_key(|node| node.address);
+
+    let mut program = Vec::new();
+    if let Some(first) = nodes.first() {
+        program.extend([0, 5, DW_LNE_SET_ADDRESS]);
+        word(&mut program, first.address, endianness);
+
+        let (mut file, mut line, mut address) = (1, 1, first.address);
+        for node in nodes {
+            if node.span.file + 1 != file {
+                file = node.span.file + 1;
+                program.push(DW_LNS_SET_FILE);
+                uleb128(&mut program, file as u64);
+            }
+            let row = node.span.row as i64 + 1;
+            if row != line {
+                program.push(DW_LNS_ADVANCE_LINE);
+                sleb128(&mut program, row - line);
+                line = row;
+            }
+            if node.address != address {
+                program.push(DW_LNS_ADVANCE_PC);
+                uleb128(&mut program, (node.address - address) as u64 / 4);
+                address = node.address;
+            }
+            program.push(DW_LNS_COPY);
+        }
+
+        // the sequence ends after the last instruction
+        program.push(DW_LNS_ADVANCE_PC);
+        uleb128(&mut program, 1);
+        program.extend([0, 1, DW_LNE_END_SEQUENCE]);
+    }
+
+    // length of the unit after its length field: version, header length, header and program
+    let mut debug_line = Vec::new();
+    word(
+        &mut debug_line,
+        (2 + 4 + header.len() + program.len()) as u32,
+        endianness,
+    );
+    half(&mut debug_line, 2, endianness);
+    word(&mut debug_line, header.len() as u32, endianness);
+    debug_line.extend(header);
+    debug_line.extend(program);
+
+    debug_line
+}
+
+/// append a string to a string table, returning the offset it starts at
+fn add_string(table: &mut Vec<u8>, string: &str) -> u32 {
+    let offset = table.len() as u32;
+    table.extend(string.as_bytes());
+    table.push(0);
+    offset
+}
+
+/// append a halfword in the given byte order
+fn half(buffer: &mut Vec<u8>, value: u16, endianness: Endianness) {
+    buffer.extend(match endianness {
+        Endianness::Little => value.to_le_bytes(),
+        Endianness::Big => value.to_be_bytes(),
+    });
+}
+
+/// append a word in the given byte order
+fn word(buffer: &mut Vec<u8>, value: u32, endianness: Endianness) {
+    buffer.extend(match endianness {
+        Endianness::Little => value.to_le_bytes(),
+        Endianness::Big => value.to_be_bytes(),
+    });
+}
+
+/// append an unsigned LEB128 number, seven bits to a byte
+fn uleb128(buffer: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer.push(byte);
+            return;
+        }
+        buffer.push(byte | 0x80);
+    }
+}
+
+/// append a signed LEB128 number, seven bits to a byte
+fn sleb128(buffer: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        // done once the rest is only copies of the sign bit
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            buffer.push(byte);
+            return;
+        }
+        buffer.push(byte | 0x80);
+    }
+}
+
+// values of header fields, named as in the ELF and DWARF specifications
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+const EV_CURRENT: u8 = 1;
+const ET_EXEC: u16 = 2;
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u32 = 1;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+const SHN_ABS: u16 = 0xFFF1;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{link_sources, linker, Options};
+
+    /// read a little endian number of len bytes at an offset
+    fn read(elf: &[u8], offset: u32, len: usize) -> u32 {
+        let offset = offset as usize;
+        elf[offset..offset + len]
+            .iter()
+            .rev()
+            .fold(0, |value, b| value << 8 | *b as u32)
+    }
+
+    #[test]
+    fn elf_test() {
+        let sources = [SourceFile::new(
+            "main.s",
+            ".text\nmain:\n    addi $t0, $zero, 5\n.data\nx: .word 7\nbuf: .space 2\n",
+        )];
+        let tree = link_sources(&sources, &Options::default()).unwrap();
+        let table = linker::symbol_table(&tree);
+        let memory = MemoryMap::default();
+        let elf = print_elf(&tree, &table, None, Endianness::Little, &memory);
+
+        assert_eq!(elf[..7], *b"\x7FELF\x01\x01\x01");
+        assert_eq!(read(&elf, 18, 2), EM_GREG as u32);
+
+        // field of a section header
+        let shoff = read(&elf, 32, 4);
+        let section = |i: u32, field: u32| read(&elf, shoff + SHDR_SIZE * i + 4 * field, 4);
+
+        // .text holds the instruction, .data the word and .bss the reserved space
+        assert_eq!(section(1, 5), 4);
+        assert_eq!(read(&elf, section(1, 4), 4), 0xC900_0005);
+        assert_eq!(section(2, 5), 4);
+        assert_eq!(read(&elf, section(2, 4), 4), 7);
+        assert_eq!((section(3, 3), section(3, 5)), (0x1000_0004, 8));
+
+        // main, x and buf each lie in their own section
+        let symtab = section(4, 4);
+        let sections: Vec<_> = (1..4)
+            .map(|i| read(&elf, symtab + SYM_SIZE * i + 14, 2))
+            .collect();
+        assert_eq!(sections, [1, 2, 3]);
+
+        // every section starts on a word boundary
+        assert!((1..7).all(|i| section(i, 4) % 4 == 0));
+
+        // line numbers add a section
+        let elf = print_elf(&tree, &table, Some(&sources), Endianness::Little, &memory);
+        assert_eq!(read(&elf, 48, 2), 8);
+    }
+
+    #[test]
+    fn leb128_test() {
+        let mut buffer = Vec::new();
+        uleb128(&mut buffer, 624485);
+        sleb128(&mut buffer, -6);
+        sleb128(&mut buffer, 64);
+        assert_eq!(buffer, [0xE5, 0x8E, 0x26, 0x7A, 0xC0, 0x00]);
+    }
+}
@@ -0,0 +1,86 @@
+use super::*;
+
+/// convert words to raw bytes, each word stored in the given byte order
+///
+/// this is the byte order bytes and halfwords were packed in,
+/// so strings come out in the order they were written
+pub fn print_raw(words: &[u32], endianness: Endianness) -> Vec<u8> {
+    words
+        .iter()
+        .flat_map(|word| match endianness {
+            Endianness::Little => word.to_le_bytes(),
+            Endianness::Big => word.to_be_bytes(),
+        })
+        .collect()
+}
+
+/// convert the binary to a single raw image of the physical memory,
+/// instructions from address 0 and data from the physical data offset of the memory map,
+/// with the gap between them filled with zeros
+///
+/// fails if the instructions reach into the data,
+/// or if the offset lies beyond the text memory, which would pad the image without bound
+pub fn print_image(
+    binary: &ProgramBinary,
+    memory: &MemoryMap,
+    endianness: Endianness,
+) -> Result<Vec<u8>, PrinterError> {
+    let offset = memory.physical_data_offset;
+    if !binary.data.is_empty() && offset > memory.text_size / 4 {
+        return Err(PrinterError::OffsetOutOfRange(offset, memory.text_size / 4));
+    }
+    if binary.instructions.len() as u64 > offset as u64 {
+        return Err(PrinterError::SegmentsOverlap(offset));
+    }
+
+    let mut words = binary.instructions.clone();
+    if !binary.data.is_empty() {
+        words.resize(offset as usize, 0);
+        words.extend(&binary.data);
+    }
+
+    Ok(print_raw(&words, endianness))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_test() {
+        let binary = ProgramBinary {
+            instructions: vec![0xC900_2000, 0x7E10_0000],
+            data: vec![0x6948],
+        };
+
+        assert_eq!(
+            print_raw(&binary.instructions, Endianness::Little),
+            vec![0x00, 0x20, 0x00, 0xC9, 0x00, 0x00, 0x10, 0x7E]
+        );
+        assert_eq!(
+            print_raw(&binary.data, Endianness::Big),
+            vec![0x00, 0x00, 0x69, 0x48]
+        );
+
+        let mut image = print_raw(&binary.instructions, Endianness::Little);
+        image.extend([0; 8]);
+        image.extend([0x48, 0x69, 0x00, 0x00]);
+        let memory = |physical_data_offset, text_size| MemoryMap {
+            physical_data_offset,
+            text_size,
+            ..MemoryMap::default()
+        };
+        assert_eq!(
+            print_image(&binary, &memory(4, 0x10), Endianness::Little),
+            Ok(image)
+        );
+        assert_eq!(
+            print_image(&binary, &memory(1, 0x10), Endianness::Little),
+            Err(PrinterError::SegmentsOverlap(1))
+        );
+        assert_eq!(
+            print_image(&binary, &memory(5, 0x10), Endianness::Little),
+            Err(PrinterError::OffsetOutOfRange(5, 4))
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use super::*;
+
+/// # Verilog radix
+///
+/// selects between the files read by $readmemh and $readmemb
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Radix {
+    /// $readmemh, each word as 8 hex digits
+    Hex,
+    /// $readmemb, each word as 32 binary digits
+    Bin,
+}
+
+/// convert the words of one memory to a Verilog memory initialisation file,
+/// one word per line
+///
+/// the file starts with an @ marker for word address 0,
+/// so it loads into the start of the memory array
+pub fn print_readmem(words: &[u32], radix: Radix) -> String {
+    let whole = 0..words.len();
+    print_readmem_with(words, std::slice::from_ref(&whole), radix)
+}
+
+/// convert the runs of words of one memory to a Verilog memory initialisation file,
+/// each run starting with an @ marker for the word address of its first word
+///
+/// words between the runs are left out, so they keep whatever the memory array held
+pub fn print_readmem_with(words: &[u32], runs: &[Range<usize>], radix: Radix) -> String {
+    let mut mem = String::new();
+
+    for run in runs {
+        mem.push_str(&format!("@{:08X}\n", run.start));
+        for word in &words[run.clone()] {
+            match radix {
+                Radix::Hex => mem.push_str(&format!("{word:08X}\n")),
+                Radix::Bin => mem.push_str(&format!("{word:032b}\n")),
+            }
+        }
+    }
+
+    mem
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readmem_test() {
+        let words = [0xC900_2000, 0x5];
+
+        assert_eq!(
+            print_readmem(&words, Radix::Hex),
+            "@00000000\nC9002000\n00000005\n"
+        );
+        assert_eq!(
+            print_readmem(&words, Radix::Bin),
+            "@00000000\n11001001000000000010000000000000\n00000000000000000000000000000101\n"
+        );
+        assert_eq!(print_readmem(&[], Radix::Hex), "@00000000\n");
+    }
+
+    // tests if gaps left by placement directives and .space are skipped with @ markers
+    #[test]
+    fn readmem_gap_test() {
+        let sources = [crate::SourceFile::new(
+            "main.s",
+            ".data\n    .word 1\n    .space 2\n    .word 2\n    .org 0x10000020\n    .byte 3\n",
+        )];
+        let tree = crate::link_sources(&sources, &crate::Options::default()).unwrap();
+        let memory = MemoryMap::default();
+
+        let (_, runs) = placed_runs(&tree, &memory);
+        assert_eq!(runs, [0..1, 3..4, 8..9]);
+
+        let binary = print_binary_with(tree, Endianness::Little, &memory);
+        assert_eq!(
+            print_readmem_with(&binary.data, &runs, Radix::Hex),
+            "@00000000\n00000001\n@00000003\n00000002\n@00000008\n00000003\n"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use super::*;
+
+/// # S-record type
+///
+/// selects the width of the addresses in the data records
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SrecType {
+    /// S1 data records ended by S9, 16 bit addresses
+    S19,
+    /// S2 data records ended by S8, 24 bit addresses
+    S28,
+    /// S3 data records ended by S7, 32 bit addresses
+    S37,
+}
+
+impl SrecType {
+    /// the narrowest type that reaches the address
+    pub fn reaching(addr: u32) -> Self {
+        match addr {
+            0..=0xFFFF => Self::S19,
+            0x1_0000..=0xFF_FFFF => Self::S28,
+            _ => Self::S37,
+        }
+    }
+
+    /// number of address bytes in the data records
+    fn address_bytes(self) -> usize {
+        match self {
+            Self::S19 => 2,
+            Self::S28 => 3,
+            Self::S37 => 4,
+        }
+    }
+}
+
+/// convert the binary to Motorola S-record format,
+/// choosing the narrowest record type that reaches the highest address
+///
+/// words are placed at the same word addresses print_hex places them at,
+/// with the data remapped to the word address offset
+pub fn print_srec(binary: ProgramBinary, offset: u32) -> String {
+    // the instructions can reach further than the data when the offset is small
+    let text_end = binary.instructions.len().checked_sub(1).map(|i| i as u32);
+    let data_end = (binary.data.len().checked_sub(1)).map(|i| offset.wrapping_add(i as u32));
+    let highest = text_end.max(data_end).unwrap_or(0);
+
+    match print_srec_with(binary, offset, SrecType::reaching(highest)) {
+        Ok(srec) => srec,
+        Err(e) => panic!("{e}. The record type reaches every address, this is a printer bug"),
+    }
+}
+
+/// convert the binary to Motorola S-record format, using the given record type
+///
+/// a header comes first, then one data record per word,
+/// a count of the data records and the terminator
+pub fn print_srec_with(
+    binary: ProgramBinary,
+    offset: u32,
+    kind: SrecType,
+) -> Result<String, PrinterError> {
+    let (data_type, end_type) = match kind {
+        SrecType::S19 => (1, 9),
+        SrecType::S28 => (2, 8),
+        SrecType::S37 => (3, 7),
+    };
+    let width = kind.address_bytes();
+
+    // the header holds no module name
+    let mut srec = print_srec_line(0, 0, 2, &[]);
+
+    let words = binary
+        .instructions
+        .into_iter()
+        .enumerate()
+        .map(|(i, word)| (i as u32, word))
+        .chain(
+            binary
+                .data
+                .into_iter()
+                .enumerate()
+                .map(|(i, word)| (offset.wrapping_add(i as u32), word)),
+        );
+
+    let mut count = 0;
+    for (addr, word) in words {
+        if width < 4 && addr >> (8 * width) != 0 {
+            return Err(PrinterError::AddressOutOfRange(addr));
+        }
+        srec.push_str(&print_srec_line(
+            data_type,
+            addr,
+            width,
+            &word.to_be_bytes(),
+        ));
+        count += 1;
+    }
+
+    // the count record is left out when the count does not fit in it
+    match count {
+        0..=0xFFFF => srec.push_str(&print_srec_line(5, count, 2, &[])),
+        0x1_0000..=0xFF_FFFF => srec.push_str(&print_srec_line(6, count, 3, &[])),
+        _ => (),
+    }
+
+    // execution starts at the beginning of the instruction memory
+    srec.push_str(&print_srec_line(end_type, 0, width, &[]));
+
+    Ok(srec)
+}
+
+/// prints a single record: its type, byte count, address, data and checksum
+///
+/// only the bottom width bytes of the address are printed
+fn print_srec_line(kind: u8, addr: u32, width: usize, data: &[u8]) -> String {
+    let address = &addr.to_be_bytes()[4 - width..];
+    let count = (width + data.len() + 1) as u8;
+
+    // ones' complement of the sum of the count, address and data bytes
+    let sum = address
+        .iter()
+        .chain(data)
+        .fold(count, |sum, b| sum.wrapping_add(*b));
+
+    let hex: String = address
+        .iter()
+        .chain(data)
+        .map(|b| format!("{b:02X}"))
+        .collect();
+
+    format!("S{kind}{count:02X}{hex}{:02X}\n", !sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::read_srec;
+
+    #[test]
+    fn srec_line_test() {
+        assert_eq!(print_srec_line(0, 0, 2, &[]), "S0030000FC\n");
+        assert_eq!(
+            print_srec_line(1, 0x0038, 2, &[0xC9, 0x00, 0x20, 0x00]),
+            "S1070038C9002000D7\n"
+        );
+        assert_eq!(print_srec_line(9, 0, 2, &[]), "S9030000FC\n");
+    }
+
+    // tests if the record type grows with the highest address and the records read back
+    #[test]
+    fn srec_roundtrip_test() {
+        let binary = ProgramBinary {
+            instructions: vec![0xC900_2000, 0x7E10_0000],
+            data: vec![1, 0xDEAD_BEEF],
+        };
+
+        let srec = print_srec(binary.clone(), 0x400);
+        assert_eq!(
+            srec,
+            "S0030000FC\n\
+             S1070000C90020000F\n\
+             S10700017E10000069\n\
+             S107040000000001F3\n\
+             S1070401DEADBEEFBB\n\
+             S5030004F8\n\
+             S9030000FC\n"
+        );
+        assert_eq!(read_srec(&srec, 0x400), Ok(binary.clone()));
+
+        for (offset, start) in [(0x1_0000, "S2"), (0x100_0000, "S3")] {
+            let srec = print_srec(binary.clone(), offset);
+            assert!(srec
+                .lines()
+                .skip(1)
+                .take(4)
+                .all(|line| line.starts_with(start)));
+            assert_eq!(read_srec(&srec, offset), Ok(binary.clone()));
+        }
+
+        assert_eq!(
+            print_srec_with(binary, 0x1_0000, SrecType::S19),
+            Err(PrinterError::AddressOutOfRange(0x1_0000))
+        );
+
+        // instructions reaching past the data they overlap still get a wide enough type
+        let binary = ProgramBinary {
+            instructions: vec![0; 0x1_0001],
+            data: vec![1],
+        };
+        let srec = print_srec(binary, 0x400);
+        assert!(srec.contains("\nS20801000000000000F6\n"));
+        assert!(srec.contains("\nS20800040000000001F2\n"));
+    }
+}
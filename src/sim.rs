@@ -0,0 +1,316 @@
+use crate::disassembler::decode;
+use crate::printer::print_binary_with;
+use crate::types::*;
+use crate::{MemoryMap, Options};
+use std::collections::{HashMap, HashSet};
+
+/// # Simulator errors
+///
+/// each holds the address of the instruction that caused it
+#[derive(Debug, PartialEq)]
+pub enum SimulatorError {
+    InvalidInstructionAddress(u32),
+    MisalignedAccess(u32, u32),
+    StepLimit(u32, u64),
+}
+
+impl std::fmt::Display for SimulatorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::InvalidInstructionAddress(pc) => {
+                write!(f, "No instruction at address 0x{pc:08X}")
+            }
+            Self::MisalignedAccess(pc, addr) => write!(
+                f,
+                "Memory access to unaligned address 0x{addr:08X} at 0x{pc:08X}"
+            ),
+            Self::StepLimit(pc, steps) => {
+                write!(
+                    f,
+                    "Program did not halt within {steps} steps, stopped at 0x{pc:08X}"
+                )
+            }
+        }
+    }
+}
+
+/// # Simulator status
+///
+/// a program halts once it runs past its last instruction or jumps to the instruction itself,
+/// which is how programs idle on the hardware
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Status {
+    Running,
+    Halted,
+}
+
+/// # Simulator
+///
+/// executes a binary instruction by instruction
+///
+/// memory is addressed in bytes and accessed in whole words.
+/// Text and data memory start at the bases of the memory map,
+/// any other address reads as 0 until written,
+/// so the stack can be placed anywhere.
+/// Writes to memory mapped I/O addresses are recorded in output
+pub struct Simulator {
+    pub registers: [u32; 16],
+    pub pc: u32,
+    pub steps: u64,
+    pub output: Vec<(u32, u32)>,
+    instructions: Vec<u32>,
+    text_base: u32,
+    memory: HashMap<u32, u32>,
+    io: HashSet<u32>,
+}
+
+impl Simulator {
+    /// simulator with the binary loaded and all registers cleared
+    pub fn new(binary: &ProgramBinary, map: &MemoryMap) -> Self {
+        let memory = binary
+            .data
+            .iter()
+            .enumerate()
+            .map(|(i, word)| (map.data_base.wrapping_add(4 * i as u32), *word))
+            .collect();
+
+        Simulator {
+            registers: [0; 16],
+            pc: map.text_base,
+            steps: 0,
+            output: Vec::new(),
+            instructions: binary.instructions.clone(),
+            text_base: map.text_base,
+            memory,
+            io: HashSet::new(),
+        }
+    }
+
+    /// simulator for a linked program, mapping the addresses of its .addr blocks as I/O
+    ///
+    /// bytes and halfwords are packed into memory in the order options select,
+    /// the segments are placed according to its memory map
+    pub fn load(tree: &ProgramTree, options: &Options) -> Self {
+        let binary = print_binary_with(tree.clone(), options.endianness, &options.memory);
+        let mut simulator = Simulator::new(&binary, &options.memory);
+
+        for node in &tree.data {
+            if node.block == Bl::Addr {
+                simulator.map_io(node.address);
+            }
+        }
+
+        simulator
+    }
+
+    /// record writes to address in output
+    pub fn map_io(&mut self, address: u32) {
+        self.io.insert(address);
+    }
+
+    /// read one word of memory
+    pub fn read(&self, address: u32) -> u32 {
+        self.memory.get(&address).copied().unwrap_or(0)
+    }
+
+    /// execute a single instruction
+    pub fn step(&mut self) -> Result<Status, SimulatorError> {
+        let pc = self.pc;
+        let end = self.text_base as u64 + 4 * self.instructions.len() as u64;
+
+        if pc as u64 == end {
+            return Ok(Status::Halted);
+        }
+
+        let index = pc.wrapping_sub(self.text_base);
+        let word = match self.instructions.get(index as usize / 4) {
+            Some(word) if index.is_multiple_of(4) => *word,
+            _ => return Err(SimulatorError::InvalidInstructionAddress(pc)),
+        };
+
+        let node = decode(word, pc);
+        let ra = self.registers[node.ra as usize];
+        let rb = self.registers[node.rb as usize];
+        let imm = node.imm as u32;
+
+        let mut next = pc.wrapping_add(4);
+
+        let result = match node.op {
+            Instr::And => Some(ra & rb),
+            Instr::Or => Some(ra | rb),
+            Instr::Xor => Some(ra ^ rb),
+            Instr::Not => Some(!ra),
+            Instr::Add => Some(ra.wrapping_add(rb)),
+            Instr::Sub => Some(ra.wrapping_sub(rb)),
+            // set if less than, comparing signed values
+            Instr::Cmp => Some(((ra as i32) < (rb as i32)) as u32),
+            // jump to ra, keeping the return address in rd
+            Instr::J => {
+                next = ra;
+                Some(pc.wrapping_add(4))
+            }
+            // branch offsets are relative to the branch itself
+            Instr::Beq | Instr::Bne => {
+                if (ra == rb) == (node.op == Instr::Beq) {
+                    next = pc.wrapping_add(imm);
+                }
+                None
+            }
+            Instr::Sl => Some(ra.checked_shl(imm).unwrap_or(0)),
+            Instr::Sr => Some(ra.checked_shr(imm).unwrap_or(0)),
+            Instr::Addi => Some(ra.wrapping_add(imm)),
+            Instr::Lui => Some(imm << 16),
+            Instr::Lw => Some(self.load_word(pc, rb)?),
+            Instr::Sw => {
+                self.store_word(pc, rb, ra)?;
+                None
+            }
+            Instr::La | Instr::Ja | Instr::Push | Instr::Pop => {
+                panic!("Pseudoinstruction decoded: {:?}. This is a bug", node.op)
+            }
+        };
+
+        if let Some(value) = result {
+            self.registers[node.rd as usize] = value;
+        }
+        // $zero always reads as zero
+        self.registers[0] = 0;
+
+        self.pc = next;
+        self.steps += 1;
+
+        if next == pc || next as u64 == end {
+            Ok(Status::Halted)
+        } else {
+            Ok(Status::Running)
+        }
+    }
+
+    /// execute instructions until the program halts, or fail after limit steps
+    pub fn run(&mut self, limit: u64) -> Result<(), SimulatorError> {
+        for _ in 0..limit {
+            if self.step()? == Status::Halted {
+                return Ok(());
+            }
+        }
+
+        Err(SimulatorError::StepLimit(self.pc, limit))
+    }
+
+    fn load_word(&self, pc: u32, address: u32) -> Result<u32, SimulatorError> {
+        if !address.is_multiple_of(4) {
+            return Err(SimulatorError::MisalignedAccess(pc, address));
+        }
+
+        Ok(self.read(address))
+    }
+
+    fn store_word(&mut self, pc: u32, address: u32, value: u32) -> Result<(), SimulatorError> {
+        if !address.is_multiple_of(4) {
+            return Err(SimulatorError::MisalignedAccess(pc, address));
+        }
+
+        if self.io.contains(&address) {
+            self.output.push((address, value));
+        }
+        self.memory.insert(address, value);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{link_sources, SourceFile, DATA_ADDRESS_OFFSET};
+
+    fn simulator(listing: &str) -> Simulator {
+        let options = Options::default();
+        let tree = link_sources(&[SourceFile::new("", listing)], &options).unwrap();
+        Simulator::load(&tree, &options)
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut sim = simulator(
+            "
+.text
+    addi $t0, $zero, 6
+    addi $t1, $zero, -2
+    add $t2, $t0, $t1
+    sub $t3, $t1, $t0
+    not $s0, $t0
+    sl $s1, $t0, 4
+    sr $s2, $t1, 28
+    cmp $a0, $t1, $t0
+    cmp $a1, $t0, $t1
+    lui $a2, 0x1234
+    addi $zero, $zero, 1
+",
+        );
+
+        assert_eq!(sim.run(100), Ok(()));
+        assert_eq!(sim.steps, 11);
+        assert_eq!(sim.registers[0], 0);
+        assert_eq!(sim.registers[11], 4);
+        assert_eq!(sim.registers[12], -8i32 as u32);
+        assert_eq!(sim.registers[6], !6);
+        assert_eq!(sim.registers[7], 96);
+        assert_eq!(sim.registers[8], 0xf);
+        assert_eq!(sim.registers[3], 1);
+        assert_eq!(sim.registers[4], 0);
+        assert_eq!(sim.registers[5], 0x1234_0000);
+    }
+
+    #[test]
+    fn memory_and_control_flow() {
+        let mut sim = simulator(
+            "
+.data
+    count: .word 5
+    display: .addr 0xffff0000
+.text
+main:
+    lui $sp, 0x8000
+    la $t0, count
+    lw $t1, $t0
+    la $t2, display
+    ja $ra, loop
+    push $t0
+    pop $s0
+end:
+    beq $zero, $zero, end
+loop:
+    sw $t1, $t2
+    addi $t1, $t1, -1
+    bne $t1, $zero, loop
+    j $zero, $ra
+",
+        );
+
+        assert_eq!(sim.run(1000), Ok(()));
+        assert_eq!(
+            sim.output,
+            (1..=5).rev().map(|i| (0xffff_0000, i)).collect::<Vec<_>>()
+        );
+        assert_eq!(sim.registers[6], DATA_ADDRESS_OFFSET);
+        assert_eq!(sim.registers[15], 0x8000_0000);
+        assert_eq!(sim.read(0x7fff_fffc), DATA_ADDRESS_OFFSET);
+        assert_eq!(sim.read(DATA_ADDRESS_OFFSET), 5);
+    }
+
+    #[test]
+    fn errors() {
+        let mut sim = simulator(".text\nloop:\n    addi $t0, $t0, 1\n    beq $zero, $zero, loop\n");
+        assert_eq!(sim.run(10), Err(SimulatorError::StepLimit(0, 10)));
+
+        let mut sim = simulator(".text\n    addi $t0, $zero, 2\n    lw $t1, $t0\n");
+        assert_eq!(sim.run(10), Err(SimulatorError::MisalignedAccess(4, 2)));
+
+        let mut sim = simulator(".text\n    addi $t0, $zero, 0x40\n    j $ra, $t0\n");
+        assert_eq!(
+            sim.run(10),
+            Err(SimulatorError::InvalidInstructionAddress(0x40))
+        );
+    }
+}
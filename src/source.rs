@@ -0,0 +1,133 @@
+/// # Source file
+///
+/// one file of the program listing, along with the name it is reported under
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub contents: String,
+}
+
+impl SourceFile {
+    pub fn new(name: &str, contents: &str) -> Self {
+        SourceFile {
+            name: name.to_string(),
+            contents: contents.to_string(),
+        }
+    }
+}
+
+/// # Span
+///
+/// indicates where in the source files a token/node/error was encountered
+///
+/// file is an index into the list of source files,
+/// row and col count from 0, len is the number of characters covered
+///
+/// spans are ordered by their position in the listing
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default)]
+pub struct Span {
+    pub file: usize,
+    pub row: u32,
+    pub col: u32,
+    pub len: u32,
+}
+
+impl Span {
+    /// extend the span up to the end of another one, if both lie on the same line
+    pub fn to(self, end: Span) -> Span {
+        if end.file == self.file && end.row == self.row && end.col >= self.col {
+            Span {
+                len: end.col + end.len - self.col,
+                ..self
+            }
+        } else {
+            self
+        }
+    }
+}
+
+/// render a message in the style of rustc diagnostics:
+/// file name, line and column, followed by the offending line with a caret underneath
+///
+/// falls back to the plain message if the span does not point into the source files
+pub fn render(sources: &[SourceFile], span: Span, message: &str) -> String {
+    let file = match sources.get(span.file) {
+        Some(file) => file,
+        None => return message.to_string(),
+    };
+
+    let line = match file.contents.lines().nth(span.row as usize) {
+        Some(line) => line,
+        None => return format!("{message}\n --> {}", file.name),
+    };
+
+    let number = (span.row + 1).to_string();
+    let gutter = " ".repeat(number.len());
+
+    // keep tabs in the padding, so the caret lines up with the source line
+    let padding: String = line
+        .chars()
+        .take(span.col as usize)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let carets = "^".repeat(span.len.max(1) as usize);
+
+    format!(
+        "{message}\n{gutter}--> {}:{}:{}\n{gutter} |\n{number} | {line}\n{gutter} | {padding}{carets}",
+        file.name,
+        span.row + 1,
+        span.col + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn span_extension() {
+        let start = Span {
+            file: 0,
+            row: 2,
+            col: 4,
+            len: 3,
+        };
+
+        let end = Span {
+            file: 0,
+            row: 2,
+            col: 12,
+            len: 2,
+        };
+
+        assert_eq!(start.to(end), Span { len: 10, ..start });
+        assert_eq!(start.to(Span { row: 3, ..end }), start);
+        assert_eq!(start.to(Span { file: 1, ..end }), start);
+    }
+
+    #[test]
+    fn rendering() {
+        let sources = vec![
+            SourceFile::new("first.s", ".text\n"),
+            SourceFile::new("second.s", ".text\nmain:\n\taddi $t0, $t0 1\n"),
+        ];
+
+        let span = Span {
+            file: 1,
+            row: 2,
+            col: 15,
+            len: 1,
+        };
+
+        assert_eq!(
+            render(&sources, span, "Error: Invalid statement"),
+            "Error: Invalid statement\n \
+             --> second.s:3:16\n  \
+             |\n\
+             3 | \taddi $t0, $t0 1\n  \
+             | \t              ^"
+        );
+
+        assert_eq!(render(&sources, Span { file: 2, ..span }, "Error"), "Error");
+    }
+}
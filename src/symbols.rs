@@ -0,0 +1,159 @@
+use crate::SourceFile;
+
+/// # Segment
+///
+/// where a symbol lives
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Segment {
+    Text,
+    Data,
+    /// constants and memory mapped .addr words, which are not placed by the assembler
+    Absolute,
+}
+
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // pad, so segments line up in the map file
+        f.pad(match self {
+            Self::Text => "text",
+            Self::Data => "data",
+            Self::Absolute => "abs",
+        })
+    }
+}
+
+/// # Symbol
+///
+/// an identifier defined in the program
+///
+/// value is the address of a label or the value of a constant,
+/// size is the number of bytes up to the next label, 0 for constants.
+/// file indexes into the source files the program was read from
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub value: i64,
+    pub segment: Segment,
+    pub size: u32,
+    pub file: usize,
+}
+
+/// every symbol of a linked program, sorted by value
+pub type SymbolTable = Vec<Symbol>;
+
+/// name of the source file a symbol was defined in
+fn file_name(sources: &[SourceFile], file: usize) -> &str {
+    sources.get(file).map_or("", |source| &source.name)
+}
+
+/// print the symbol table as a map file, one symbol per line
+pub fn print_map(table: &SymbolTable, sources: &[SourceFile]) -> String {
+    let mut map = format!(
+        "{:<10}{:<10}{:<9}{:<24}{}\n",
+        "Value", "Size", "Segment", "Symbol", "File"
+    );
+
+    for symbol in table {
+        let line = format!(
+            "{:08X}  {:08X}  {:<7}  {:<22}  {}",
+            symbol.value as u32,
+            symbol.size,
+            symbol.segment,
+            symbol.name,
+            file_name(sources, symbol.file)
+        );
+        map.push_str(line.trim_end());
+        map.push('\n');
+    }
+
+    map
+}
+
+/// print the symbol table as a JSON array of objects, one symbol per line
+pub fn print_json(table: &SymbolTable, sources: &[SourceFile]) -> String {
+    let symbols: Vec<String> = table
+        .iter()
+        .map(|symbol| {
+            format!(
+                "  {{\"name\": {}, \"value\": {}, \"segment\": \"{}\", \"size\": {}, \"file\": {}}}",
+                json_string(&symbol.name),
+                symbol.value,
+                symbol.segment,
+                symbol.size,
+                json_string(file_name(sources, symbol.file))
+            )
+        })
+        .collect();
+
+    if symbols.is_empty() {
+        "[]\n".to_string()
+    } else {
+        format!("[\n{}\n]\n", symbols.join(",\n"))
+    }
+}
+
+/// quote a string for JSON, escaping what needs escaping
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printing() {
+        let sources = [
+            SourceFile::new("<crt0>", ""),
+            SourceFile::new("dir\\\"main\".s", ""),
+        ];
+        let table = vec![
+            Symbol {
+                name: "SIZE".to_string(),
+                value: -4,
+                segment: Segment::Absolute,
+                size: 0,
+                file: 1,
+            },
+            Symbol {
+                name: "main".to_string(),
+                value: 0x24,
+                segment: Segment::Text,
+                size: 8,
+                file: 1,
+            },
+        ];
+
+        assert_eq!(
+            print_map(&table, &sources),
+            "\
+Value     Size      Segment  Symbol                  File
+FFFFFFFC  00000000  abs      SIZE                    dir\\\"main\".s
+00000024  00000008  text     main                    dir\\\"main\".s
+"
+        );
+        assert_eq!(
+            print_json(&table, &sources),
+            "\
+[
+  {\"name\": \"SIZE\", \"value\": -4, \"segment\": \"abs\", \"size\": 0, \"file\": \"dir\\\\\\\"main\\\".s\"},
+  {\"name\": \"main\", \"value\": 36, \"segment\": \"text\", \"size\": 8, \"file\": \"dir\\\\\\\"main\\\".s\"}
+]
+"
+        );
+        assert_eq!(print_json(&vec![], &sources), "[]\n");
+    }
+}
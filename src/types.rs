@@ -1,3 +1,15 @@
+use crate::expr::{Expr, Op};
+use crate::source::Span;
+use std::ops::RangeInclusive;
+
+/// values that fit in a 32 bit word, whether it is read as signed or unsigned
+pub const WORD_RANGE: RangeInclusive<i64> = -0x8000_0000..=0xFFFF_FFFF;
+
+/// register names, indexed by register number
+pub const REGISTER_NAMES: [&str; 16] = [
+    "zero", "at", "v", "a0", "a1", "a2", "s0", "s1", "s2", "t0", "t1", "t2", "t3", "gv", "ra", "sp",
+];
+
 /// # Token types
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Token {
@@ -7,8 +19,11 @@ pub enum Token {
     Register(u8),
     Identifier(String),
     Immediate(i64),
+    String(Vec<u8>),
     Block(Bl),
-    Err,
+    Operator(Op),
+    LParen,
+    RParen,
 }
 
 /// # Block annotation types
@@ -19,6 +34,14 @@ pub enum Bl {
     Addr,
     Space,
     Word,
+    Equ,
+    Set,
+    Ascii,
+    Asciiz,
+    Byte,
+    Half,
+    Align,
+    Org,
 }
 
 /// # Instructions
@@ -47,6 +70,25 @@ pub enum Instr {
     Pop,
 }
 
+impl Instr {
+    /// range of values the immediate operand of the instruction can take
+    ///
+    /// instructions without an immediate operand only accept 0
+    pub fn imm_range(&self) -> RangeInclusive<i64> {
+        match self {
+            // sign extended 16 bit immediate, branches use it as a byte offset
+            Instr::Addi | Instr::Beq | Instr::Bne => -0x8000..=0x7fff,
+            // upper half of a register
+            Instr::Lui => 0..=0xffff,
+            // shift amount
+            Instr::Sl | Instr::Sr => 0..=31,
+            // full address, split across several instructions
+            Instr::La | Instr::Ja => WORD_RANGE,
+            _ => 0..=0,
+        }
+    }
+}
+
 /// # Abstract instruction representation
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct InstructionNode {
@@ -56,18 +98,53 @@ pub struct InstructionNode {
     pub rb: u8,
     pub imm: i64,
     pub identifier: String,
-    pub imm_identifier: String,
+    /// where the identifier was defined as a label, default if there is none
+    pub label_span: Span,
+    pub imm_expr: Option<Expr>,
     pub address: u32,
+    pub placement: Option<Placement>,
+    pub span: Span,
+}
+
+/// # Placement of code
+///
+/// how the placement directives in front of an instruction fixed its address,
+/// so it can be placed again when the code before it moves
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Placement {
+    /// placed at this address with .org
+    Org(u32),
+    /// aligned to a multiple of this many bytes with .align
+    Align(u32),
 }
 
 /// # abstract data block representation
+///
+/// payload holds the bytes of .ascii, .asciiz and .byte blocks and the halfwords of .half blocks,
+/// which are packed into num words when the binary is printed
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct DataNode {
     pub identifier: String,
+    /// where the identifier was defined as a label, default if there is none
+    pub label_span: Span,
     pub address: u32,
     pub block: Bl,
     pub data: i64,
+    pub data_expr: Option<Expr>,
+    pub payload: Vec<u32>,
     pub num: u32,
+    pub span: Span,
+}
+
+/// # abstract constant definition representation
+///
+/// constants defined with .set are redefinable, those defined with .equ are not
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConstantNode {
+    pub identifier: String,
+    pub value: i64,
+    pub redefinable: bool,
+    pub span: Span,
 }
 
 /// # Abstract representation of the whole program
@@ -75,6 +152,7 @@ pub struct DataNode {
 pub struct ProgramTree {
     pub instructions: Vec<InstructionNode>,
     pub data: Vec<DataNode>,
+    pub constants: Vec<ConstantNode>,
 }
 
 /// # Compiled binary